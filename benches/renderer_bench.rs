@@ -1,19 +1,76 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use image::ImageBuffer;
+use image::{ImageBuffer, Rgba};
+use nalgebra::Vector3;
 
+use lib::consts::{HEIGHT, WIDTH};
 use lib::img_io::*;
+use lib::line::*;
 use lib::render::*;
+use lib::scene::{Camera, Light, Scene, Transform};
+use lib::texture::ColorSpace;
 
 pub fn renderer_benchmark(c: &mut Criterion) {
     let model = load_obj("obj/african_head.obj");
+    let texture = load_image("obj/african_head_diffuse.tga", ColorSpace::Srgb).unwrap();
+    let specular_tex = load_image("obj/african_head_spec.tga", ColorSpace::Linear).unwrap();
+    let normal_tex = load_image("obj/african_head_nm.tga", ColorSpace::Linear).unwrap();
+    let model = WModel::new(model, Some(texture), Some(specular_tex), Some(normal_tex));
+    let mut scene = Scene::new(Camera::new(
+        Vector3::new(1.0, 1.0, 2.5),
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        std::f32::consts::FRAC_PI_4,
+        WIDTH as f32 / HEIGHT as f32,
+    ));
+    scene.add_light(Light::new(Vector3::new(0.0, 3.0, 3.0)));
+    scene.add(&model, Transform::default());
 
-    // c.bench_function("line", |b| {
-    //     b.iter(|| line(13, 20, 600, 400, &mut ImageBuffer::new(800, 600)))
-    // });
+    c.bench_function("line", |b| {
+        b.iter(|| {
+            line(
+                13,
+                20,
+                600,
+                400,
+                &mut ImageBuffer::new(800, 600),
+                Rgba([255, 255, 255, 255]),
+            )
+        })
+    });
 
-    // c.bench_function("obj", |b| {
-    //     b.iter(|| render_obj_line(&model, &mut ImageBuffer::new(800, 600)))
-    // });
+    c.bench_function("line_aa", |b| {
+        b.iter(|| {
+            line_aa(
+                13.0,
+                20.0,
+                600.0,
+                400.0,
+                &mut ImageBuffer::new(800, 600),
+                Rgba([255, 255, 255, 255]),
+            )
+        })
+    });
+
+    c.bench_function("obj", |b| {
+        b.iter(|| {
+            render_scene(
+                &scene,
+                &mut ImageBuffer::new(WIDTH, HEIGHT),
+                &RenderConfig::default(),
+            )
+        })
+    });
+
+    c.bench_function("wireframe", |b| {
+        b.iter(|| {
+            render_wireframe(
+                &scene,
+                &mut ImageBuffer::new(WIDTH, HEIGHT),
+                &Wireframe::default(),
+                &RenderConfig::default(),
+            )
+        })
+    });
 }
 
 criterion_group!(benches, renderer_benchmark,);
@@ -0,0 +1,150 @@
+use nalgebra::{Vector3, Vector4};
+
+// vertex of a clipped polygon, `bar` holds its weights relative to the original triangle
+#[derive(Clone, Copy)]
+pub struct ClipVertex {
+    pub pos: Vector4<f32>,
+    pub bar: Vector3<f32>,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            pos: self.pos + (other.pos - self.pos) * t,
+            bar: self.bar + (other.bar - self.bar) * t,
+        }
+    }
+}
+
+const W_EPSILON: f32 = 1e-5;
+
+// signed distance to each clip plane, a vertex is inside when it is non-negative
+const PLANES: [fn(&Vector4<f32>) -> f32; 7] = [
+    |p| p.w - W_EPSILON,
+    |p| p.w + p.x,
+    |p| p.w - p.x,
+    |p| p.w + p.y,
+    |p| p.w - p.y,
+    |p| p.w + p.z,
+    |p| p.w - p.z,
+];
+
+// Sutherland-Hodgman clipping of a clip space triangle against the view frustum.
+// Returns the vertices of the visible convex polygon, empty if nothing is visible.
+pub fn clip_triangle(pts: &[Vector4<f32>; 3]) -> Vec<ClipVertex> {
+    let mut polygon: Vec<ClipVertex> = (0..3)
+        .map(|i| ClipVertex {
+            pos: pts[i],
+            bar: Vector3::ith(i, 1.0),
+        })
+        .collect();
+
+    // trivially accept triangles which lie inside every plane
+    if pts
+        .iter()
+        .all(|p| PLANES.iter().all(|plane| plane(p) >= 0.0))
+    {
+        return polygon;
+    }
+
+    for plane in PLANES.iter() {
+        if polygon.is_empty() {
+            break;
+        }
+        let input = std::mem::take(&mut polygon);
+        for (i, cur) in input.iter().enumerate() {
+            let next = &input[(i + 1) % input.len()];
+            let d_cur = plane(&cur.pos);
+            let d_next = plane(&next.pos);
+            if d_cur >= 0.0 {
+                polygon.push(*cur);
+            }
+            if (d_cur >= 0.0) != (d_next >= 0.0) {
+                polygon.push(cur.lerp(next, d_cur / (d_cur - d_next)));
+            }
+        }
+    }
+
+    if polygon.len() < 3 {
+        polygon.clear();
+    }
+    polygon
+}
+
+// clip the clip space segment from `a` to `b` against the view frustum,
+// returns the visible parameter range along the segment
+pub fn clip_line(a: &Vector4<f32>, b: &Vector4<f32>) -> Option<(f32, f32)> {
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for plane in PLANES.iter() {
+        let (da, db) = (plane(a), plane(b));
+        if da < 0.0 && db < 0.0 {
+            return None;
+        }
+        if da < 0.0 {
+            t0 = t0.max(da / (da - db));
+        } else if db < 0.0 {
+            t1 = t1.min(da / (da - db));
+        }
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangle_behind_camera_is_dropped() {
+        let pts = [
+            Vector4::new(0.0, 0.0, 0.5, -1.0),
+            Vector4::new(0.5, 0.0, 0.5, -1.0),
+            Vector4::new(0.0, 0.5, 0.5, -2.0),
+        ];
+        assert!(clip_triangle(&pts).is_empty());
+    }
+
+    #[test]
+    fn triangle_inside_is_unchanged() {
+        let pts = [
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(0.5, 0.0, 0.2, 1.0),
+            Vector4::new(0.0, 0.5, -0.2, 2.0),
+        ];
+        let polygon = clip_triangle(&pts);
+        assert_eq!(polygon.len(), 3);
+        for (i, v) in polygon.iter().enumerate() {
+            assert_eq!(v.pos, pts[i]);
+            assert_eq!(v.bar, Vector3::ith(i, 1.0));
+        }
+    }
+
+    #[test]
+    fn triangle_across_near_plane_becomes_quad() {
+        // the third corner lies in front of the near plane z = -w
+        let pts = [
+            Vector4::new(0.0, 0.0, 0.0, 1.0),
+            Vector4::new(0.5, 0.0, 0.0, 1.0),
+            Vector4::new(0.0, 0.5, -3.0, 1.0),
+        ];
+        let polygon = clip_triangle(&pts);
+        assert_eq!(polygon.len(), 4);
+        for v in polygon.iter() {
+            assert!((v.bar.sum() - 1.0).abs() < 1e-6);
+            let p = pts[0] * v.bar.x + pts[1] * v.bar.y + pts[2] * v.bar.z;
+            assert!((p - v.pos).norm() < 1e-6);
+            assert!(v.pos.w + v.pos.z >= -1e-6);
+        }
+    }
+
+    #[test]
+    fn line_is_cut_at_the_frustum() {
+        let a = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let b = Vector4::new(3.0, 0.0, 0.0, 1.0);
+        let (t0, t1) = clip_line(&a, &b).unwrap();
+        assert_eq!(t0, 0.0);
+        assert!((t1 - 1.0 / 3.0).abs() < 1e-6);
+
+        let c = Vector4::new(2.0, 0.0, 0.0, 1.0);
+        assert_eq!(clip_line(&c, &b), None);
+    }
+}
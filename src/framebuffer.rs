@@ -0,0 +1,296 @@
+use image::{ImageBuffer, Rgba};
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::texture::{linear_to_srgb, srgb_to_linear};
+
+// high dynamic range RGB, 1.0 is the brightest displayable value before tone mapping
+pub type Color = Vector3<f32>;
+
+// high dynamic range RGB with a straight, not premultiplied, alpha in w
+pub type ColorAlpha = Vector4<f32>;
+
+// how a transparent fragment `src` is combined with the color `dst` already in the buffer.
+// The alpha of the result is the usual over operator in every mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Alpha,    // src * a + dst * (1 - a)
+    Additive, // dst + src * a, for glows and light shafts
+    Multiply, // dst tinted by src, for colored glass
+}
+
+impl BlendMode {
+    pub fn apply(&self, src: ColorAlpha, dst: ColorAlpha) -> ColorAlpha {
+        let a = src.w.clamp(0.0, 1.0);
+        let (s, d) = (src.xyz(), dst.xyz());
+        let color = match self {
+            BlendMode::Alpha => s * a + d * (1.0 - a),
+            BlendMode::Additive => d + s * a,
+            BlendMode::Multiply => d.component_mul(&Color::repeat(1.0).lerp(&s, a)),
+        };
+        color.push(a + dst.w * (1.0 - a))
+    }
+}
+
+// operator mapping high dynamic range colors into the displayable range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    Aces, // Narkowicz's fit of the ACES filmic curve
+}
+
+impl ToneMapping {
+    pub fn apply(&self, v: f32) -> f32 {
+        let v = v.max(0.0);
+        match self {
+            ToneMapping::Clamp => v.min(1.0),
+            ToneMapping::Reinhard => v / (1.0 + v),
+            ToneMapping::Aces => {
+                ((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+// number of coverage and depth samples per pixel, shading still runs once per pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Msaa {
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub fn samples(&self) -> usize {
+        self.offsets().len()
+    }
+
+    // sample positions relative to the pixel, the standard D3D patterns in 1/16 pixel
+    pub fn offsets(&self) -> &'static [(f32, f32)] {
+        match self {
+            Msaa::Off => &[(0.0, 0.0)],
+            Msaa::X2 => &[(4.0 / 16.0, 4.0 / 16.0), (-4.0 / 16.0, -4.0 / 16.0)],
+            Msaa::X4 => &[
+                (-2.0 / 16.0, -6.0 / 16.0),
+                (6.0 / 16.0, -2.0 / 16.0),
+                (-6.0 / 16.0, 2.0 / 16.0),
+                (2.0 / 16.0, 6.0 / 16.0),
+            ],
+            Msaa::X8 => &[
+                (1.0 / 16.0, -3.0 / 16.0),
+                (-1.0 / 16.0, 3.0 / 16.0),
+                (5.0 / 16.0, 1.0 / 16.0),
+                (-3.0 / 16.0, -5.0 / 16.0),
+                (-5.0 / 16.0, 5.0 / 16.0),
+                (-7.0 / 16.0, -1.0 / 16.0),
+                (3.0 / 16.0, 7.0 / 16.0),
+                (7.0 / 16.0, -7.0 / 16.0),
+            ],
+        }
+    }
+}
+
+// color and depth storage of a render pass, the samples of a pixel are stored next to each other
+pub struct FrameBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub samples: usize,
+    pub color: Vec<ColorAlpha>,
+    pub depth: Vec<f32>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: u32, height: u32, samples: usize) -> Self {
+        let len = (width * height) as usize * samples;
+        FrameBuffer {
+            width,
+            height,
+            samples,
+            color: vec![ColorAlpha::zeros(); len],
+            depth: vec![f32::MIN; len],
+        }
+    }
+
+    // start from the content of an sRGB image, every sample gets the linear color of its pixel
+    pub fn from_image(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, samples: usize) -> Self {
+        let mut fb = FrameBuffer::new(image.width(), image.height(), samples);
+        for (i, pixel) in image.pixels().enumerate() {
+            let [r, g, b, a] = pixel.0.map(|c| c as f32 / 255.0);
+            let color = ColorAlpha::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a);
+            fb.color[i * samples..(i + 1) * samples].fill(color);
+        }
+        fb
+    }
+
+    // View space position of the first sample of every pixel, None for the background.
+    // `inv_screen` is the inverse of the viewport times projection the buffer was rendered with.
+    pub fn view_positions(&self, inv_screen: &Matrix4<f32>) -> Vec<Option<Vector3<f32>>> {
+        (0..(self.width * self.height) as usize)
+            .map(|i| {
+                let z = self.depth[i * self.samples];
+                if z == f32::MIN {
+                    return None;
+                }
+                let (x, y) = (i % self.width as usize, i / self.width as usize);
+                let p = inv_screen * Vector4::new(x as f32 + 0.5, y as f32 + 0.5, z, 1.0);
+                Some(p.xyz() / p.w)
+            })
+            .collect()
+    }
+
+    // View space normals facing the camera, reconstructed from the positions of neighboring
+    // pixels. The smaller difference is taken on each axis so that edges do not bend them.
+    pub fn view_normals(&self, positions: &[Option<Vector3<f32>>]) -> Vec<Option<Vector3<f32>>> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        (0..positions.len())
+            .map(|i| {
+                let p = positions[i]?;
+                let (x, y) = (i as i32 % width, i as i32 / width);
+                let neighbor = |dx: i32, dy: i32| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        return None;
+                    }
+                    positions[(nx + ny * width) as usize].map(|q| q - p)
+                };
+                let closest = |a: Option<Vector3<f32>>, b: Option<Vector3<f32>>| match (a, b) {
+                    (Some(a), Some(b)) if b.norm() < a.norm() => Some(-b),
+                    (Some(a), _) => Some(a),
+                    (None, b) => b.map(|b| -b),
+                };
+                let dx = closest(neighbor(1, 0), neighbor(-1, 0))?;
+                let dy = closest(neighbor(0, 1), neighbor(0, -1))?;
+                let n = dx.cross(&dy).normalize();
+                if !n.iter().all(|c| c.is_finite()) {
+                    return None;
+                }
+                Some(if n.dot(&p) > 0.0 { -n } else { n })
+            })
+            .collect()
+    }
+
+    // Scale every sample by `exposure`, tone map it and average the samples of every pixel
+    // into the image. Samples are tone mapped before averaging so that bright samples do not
+    // swallow the anti-aliased edges. The average is encoded with the sRGB curve before it is
+    // rounded to 8 bits, so that dark tones keep their precision. Alpha is averaged as is.
+    pub fn resolve(
+        &self,
+        image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        tone_mapping: ToneMapping,
+        exposure: f32,
+    ) {
+        for (i, pixel) in image.pixels_mut().enumerate() {
+            let mut sum = ColorAlpha::zeros();
+            for color in self.color[i * self.samples..(i + 1) * self.samples].iter() {
+                let rgb = (color.xyz() * exposure).map(|c| tone_mapping.apply(c));
+                sum += rgb.push(color.w.clamp(0.0, 1.0));
+            }
+            let avg = sum / self.samples as f32;
+            let srgb = avg.xyz().map(linear_to_srgb).push(avg.w);
+            *pixel = Rgba(srgb.data.0[0].map(|c| (c * 255.0).round() as u8));
+        }
+    }
+}
+
+// rectangular region of a frame buffer with its own copy of the samples
+pub struct Tile {
+    pub x0: i32,
+    pub y0: i32,
+    pub width: i32,
+    pub height: i32,
+    pub samples: usize,
+    pub color: Vec<ColorAlpha>,
+    pub depth: Vec<f32>,
+}
+
+impl Tile {
+    // copy the region out of the frame buffer
+    pub fn new(x0: i32, y0: i32, width: i32, height: i32, fb: &FrameBuffer) -> Self {
+        let samples = fb.samples;
+        let mut color = Vec::with_capacity((width * height) as usize * samples);
+        let mut depth = Vec::with_capacity((width * height) as usize * samples);
+        for y in y0..y0 + height {
+            let start = (x0 + y * fb.width as i32) as usize * samples;
+            let end = start + width as usize * samples;
+            color.extend_from_slice(&fb.color[start..end]);
+            depth.extend_from_slice(&fb.depth[start..end]);
+        }
+        Tile {
+            x0,
+            y0,
+            width,
+            height,
+            samples,
+            color,
+            depth,
+        }
+    }
+
+    // copy the region back into the frame buffer
+    pub fn write_back(&self, fb: &mut FrameBuffer) {
+        let row = self.width as usize * self.samples;
+        for y in 0..self.height {
+            let start = (self.x0 + (self.y0 + y) * fb.width as i32) as usize * self.samples;
+            let src = y as usize * row;
+            fb.color[start..start + row].copy_from_slice(&self.color[src..src + row]);
+            fb.depth[start..start + row].copy_from_slice(&self.depth[src..src + row]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_modes() {
+        let src = ColorAlpha::new(0.5, 1.0, 0.25, 0.5);
+        let dst = ColorAlpha::new(0.2, 0.4, 0.8, 0.5);
+        for (blend, expected) in [
+            (BlendMode::Alpha, ColorAlpha::new(0.35, 0.7, 0.525, 0.75)),
+            (BlendMode::Additive, ColorAlpha::new(0.45, 0.9, 0.925, 0.75)),
+            (BlendMode::Multiply, ColorAlpha::new(0.15, 0.4, 0.5, 0.75)),
+        ] {
+            let out = blend.apply(src, dst);
+            assert!((out - expected).norm() < 1e-6, "{blend:?}: {out:?}");
+            // a transparent source leaves the destination as it is
+            let out = blend.apply(src.xyz().push(0.0), dst);
+            assert!((out - dst).norm() < 1e-6, "{blend:?}: {out:?}");
+            // alpha is clamped, an opaque source covers the destination alpha
+            assert_eq!(blend.apply(src.xyz().push(2.0), dst).w, 1.0, "{blend:?}");
+        }
+        assert_eq!(
+            BlendMode::Alpha.apply(src.xyz().push(1.0), dst),
+            src.xyz().push(1.0)
+        );
+    }
+
+    #[test]
+    fn tone_mapping_curves() {
+        for tone_mapping in [ToneMapping::Clamp, ToneMapping::Reinhard, ToneMapping::Aces] {
+            assert_eq!(tone_mapping.apply(0.0), 0.0);
+            assert_eq!(tone_mapping.apply(-1.0), 0.0);
+            let large = tone_mapping.apply(1e6);
+            assert!(large <= 1.0 && large > 0.99, "{tone_mapping:?}: {large}");
+        }
+        assert_eq!(ToneMapping::Clamp.apply(1.0), 1.0);
+        assert_eq!(ToneMapping::Reinhard.apply(1.0), 0.5);
+        assert!((ToneMapping::Aces.apply(1.0) - 2.54 / 3.16).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resolve_tone_maps_samples_before_averaging() {
+        let mut fb = FrameBuffer::new(1, 1, 2);
+        fb.color = vec![
+            ColorAlpha::new(4.0, 4.0, 4.0, 1.0),
+            ColorAlpha::new(0.0, 0.0, 0.0, 0.0),
+        ];
+        let mut image = ImageBuffer::new(1, 1);
+        fb.resolve(&mut image, ToneMapping::Clamp, 1.0);
+
+        // clamped to 1 and 0 first, averaging 4 and 0 would saturate at 1 instead
+        let half = (linear_to_srgb(0.5) * 255.0).round() as u8;
+        assert_eq!(image.get_pixel(0, 0).0, [half, half, half, 128]);
+    }
+}
@@ -1,4 +1,4 @@
-use nalgebra::{Matrix4, Vector3};
+use nalgebra::{Matrix3, Matrix4, Vector3};
 
 pub fn get_viewport(x: f32, y: f32, w: f32, h: f32) -> Matrix4<f32> {
     let mut m: Matrix4<f32> = Matrix4::identity();
@@ -42,3 +42,57 @@ pub fn get_projection(fovy: f32, aspect: f32, n: f32) -> Matrix4<f32> {
 
     m
 }
+
+// orthographic projection of the box [l, r] x [b, t] x [-f, -n] in view space,
+// depth grows towards the viewer like get_projection
+pub fn get_orthographic(l: f32, r: f32, b: f32, t: f32, n: f32, f: f32) -> Matrix4<f32> {
+    let mut m: Matrix4<f32> = Matrix4::identity();
+
+    m[(0, 0)] = 2.0 / (r - l);
+    m[(1, 1)] = 2.0 / (t - b);
+    m[(2, 2)] = 2.0 / (f - n);
+    m[(0, 3)] = -(r + l) / (r - l);
+    m[(1, 3)] = -(t + b) / (t - b);
+    m[(2, 3)] = (f + n) / (f - n);
+
+    m
+}
+
+// Matrix taking normals through the transform `m`, the inverse transpose of its linear part.
+// A singular `m`, e.g. with a zero scale axis, falls back to the cofactor matrix, which has
+// the same direction where the inverse exists and stays defined where it does not.
+pub fn get_normal_matrix(m: &Matrix4<f32>) -> Matrix4<f32> {
+    match m.transpose().try_inverse() {
+        Some(inv) => inv,
+        None => cofactor(m).to_homogeneous(),
+    }
+}
+
+// cofactor matrix of the upper 3x3 block, the inverse transpose times the determinant
+fn cofactor(m: &Matrix4<f32>) -> Matrix3<f32> {
+    let c = |i| m.fixed_view::<3, 1>(0, i).into_owned();
+    let (c0, c1, c2) = (c(0), c(1), c(2));
+    Matrix3::from_columns(&[c1.cross(&c2), c2.cross(&c0), c0.cross(&c1)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn singular_normal_matrix_keeps_the_flattened_normal() {
+        let scale = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 0.0, 1.0));
+        let n = get_normal_matrix(&scale) * Vector3::new(0.3, 0.5, 0.2).to_homogeneous();
+        assert_eq!(n.xyz().normalize(), Vector3::y());
+
+        // same direction as the inverse transpose when the matrix is invertible
+        let m = Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 0.5, 1.0))
+            * Matrix4::from_euler_angles(0.3, 0.2, 0.1);
+        let v = Vector3::new(0.1, 0.7, -0.4);
+        let a = (get_normal_matrix(&m) * v.to_homogeneous())
+            .xyz()
+            .normalize();
+        let b = (cofactor(&m) * v).normalize();
+        assert!((a - b).norm() < 1e-5);
+    }
+}
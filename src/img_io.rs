@@ -1,34 +1,51 @@
-use image::imageops::flip_vertical_in_place;
-use image::{ImageBuffer, ImageReader, Rgb};
+use image::imageops::{flip_vertical, flip_vertical_in_place};
+use image::{ImageBuffer, ImageReader, Rgba};
 use lazy_static::lazy_static;
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 use std::collections::HashMap;
 use std::path::Path;
 
-use crate::consts::LIGHT_DIR;
+use crate::{
+    framebuffer::ColorAlpha,
+    geometry,
+    material::Material,
+    sampler::Sampler,
+    texture::{ColorSpace, Texture},
+};
 
-pub fn init_image(width: u32, height: u32) -> ImageBuffer<image::Rgb<u8>, Vec<u8>> {
-    let mut image: ImageBuffer<image::Rgb<u8>, Vec<u8>> = ImageBuffer::new(width, height);
+pub fn init_image(width: u32, height: u32) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+    let mut image: ImageBuffer<image::Rgba<u8>, Vec<u8>> = ImageBuffer::new(width, height);
     for x in 0..image.width() {
         for y in 0..image.height() {
-            image.put_pixel(x, y, image::Rgb([0, 0, 0]))
+            image.put_pixel(x, y, image::Rgba([0, 0, 0, 255]))
         }
     }
     image
 }
 
-pub fn output_image(filename: &str, image: &mut ImageBuffer<image::Rgb<u8>, Vec<u8>>) {
-    flip_vertical_in_place(image);
-    image.save(filename).unwrap();
+pub fn fill_image(
+    width: u32,
+    height: u32,
+    color: Rgba<u8>,
+) -> ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_pixel(width, height, color)
+}
+
+// save a y-up image, the image itself is left untouched
+pub fn output_image(filename: &str, image: &ImageBuffer<image::Rgba<u8>, Vec<u8>>) {
+    flip_vertical(image).save(filename).unwrap();
     println!("Saved image!");
 }
 
+// load a texture and generate its mip levels, `color_space` is Srgb for color textures
+// and Linear for data textures
 pub fn load_image(
     filename: &str,
-) -> Result<ImageBuffer<image::Rgb<u8>, Vec<u8>>, Box<dyn std::error::Error>> {
-    let mut img = ImageReader::open(filename)?.decode()?.to_rgb8();
+    color_space: ColorSpace,
+) -> Result<Texture, Box<dyn std::error::Error>> {
+    let mut img = ImageReader::open(filename)?.decode()?.to_rgba8();
     flip_vertical_in_place(&mut img);
-    Ok(img)
+    Ok(Texture::new(img, color_space))
 }
 
 pub fn load_obj(filename: &str) -> tobj::Model {
@@ -36,46 +53,184 @@ pub fn load_obj(filename: &str) -> tobj::Model {
     model.first().unwrap().clone()
 }
 
+// 2x2 square centered at the origin in the xz plane facing +y, useful as a floor
+pub fn make_plane() -> tobj::Model {
+    let mesh = tobj::Mesh {
+        positions: vec![
+            -1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.0, -1.0, -1.0, 0.0, -1.0,
+        ],
+        normals: vec![0.0, 1.0, 0.0],
+        texcoords: vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+        indices: vec![0, 1, 2, 0, 2, 3],
+        texcoord_indices: vec![0, 1, 2, 0, 2, 3],
+        normal_indices: vec![0; 6],
+        ..Default::default()
+    };
+    tobj::Model::new(mesh, "plane".to_string())
+}
+
 lazy_static! {
-    pub static ref COLORS: HashMap<String, image::Rgb<u8>> = {
+    pub static ref COLORS: HashMap<String, image::Rgba<u8>> = {
         let mut colors = HashMap::new();
-        colors.insert("black".to_string(), Rgb([0, 0, 0]));
-        colors.insert("white".to_string(), Rgb([255, 255, 255]));
-        colors.insert("red".to_string(), Rgb([255, 0, 0]));
-        colors.insert("green".to_string(), Rgb([0, 255, 0]));
-        colors.insert("blue".to_string(), Rgb([0, 0, 255]));
+        colors.insert("black".to_string(), Rgba([0, 0, 0, 255]));
+        colors.insert("white".to_string(), Rgba([255, 255, 255, 255]));
+        colors.insert("red".to_string(), Rgba([255, 0, 0, 255]));
+        colors.insert("green".to_string(), Rgba([0, 255, 0, 255]));
+        colors.insert("blue".to_string(), Rgba([0, 0, 255, 255]));
         colors
     };
 }
 
+// how the normal texture of a model is interpreted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalMapSpace {
+    Object,  // texels are normals in model space
+    Tangent, // texels are normals relative to the tangent frame of the surface
+}
+
+// Per corner tangents of a mesh, built the way MikkTSpace builds them: the tangent of every
+// face, derived from its uv gradients, is projected onto the plane of each corner normal and
+// summed weighted by the corner angle over the corners sharing position, normal, uv and
+// handedness. `w` holds the handedness, the bitangent is `w * normal.cross(tangent)`.
+fn compute_tangents(
+    model: &tobj::Model,
+    positions: &[[Vector3<f32>; 3]],
+    tex_uv: &[[Vector2<f32>; 3]],
+    normals: &[[Vector3<f32>; 3]],
+) -> Vec<[Vector4<f32>; 3]> {
+    let mesh = &model.mesh;
+    let key = |i: usize, j: usize, right_handed: bool| {
+        (
+            mesh.indices[3 * i + j],
+            mesh.normal_indices.get(3 * i + j).copied(),
+            mesh.texcoord_indices.get(3 * i + j).copied(),
+            right_handed,
+        )
+    };
+    // per face tangent and bitangent, None where the uv mapping is degenerate
+    let gradients: Vec<Option<(Vector3<f32>, Vector3<f32>)>> = positions
+        .iter()
+        .zip(tex_uv.iter())
+        .map(|(p, uv)| {
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (d1, d2) = (uv[1] - uv[0], uv[2] - uv[0]);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            (det.abs() >= 1e-12)
+                .then(|| ((e1 * d2.y - e2 * d1.y) / det, (e2 * d1.x - e1 * d2.x) / det))
+        })
+        .collect();
+    // projected onto the plane of the corner normal, mirrored mappings are left handed
+    let corner = |i: usize, j: usize| {
+        let (s, t) = gradients[i]?;
+        let n = normals[i][j];
+        let s = (s - n * n.dot(&s)).try_normalize(1e-12)?;
+        Some((s, n.cross(&s).dot(&t) >= 0.0))
+    };
+
+    let mut sums = HashMap::<_, Vector3<f32>>::new();
+    for (i, p) in positions.iter().enumerate() {
+        for j in 0..3 {
+            let a = p[(j + 1) % 3] - p[j];
+            let b = p[(j + 2) % 3] - p[j];
+            let angle = a.angle(&b);
+            if let (Some((s, right_handed)), true) = (corner(i, j), angle.is_finite()) {
+                *sums.entry(key(i, j, right_handed)).or_default() += s * angle;
+            }
+        }
+    }
+
+    (0..positions.len())
+        .map(|i| {
+            std::array::from_fn(|j| {
+                let n = normals[i][j];
+                let right_handed = corner(i, j).is_none_or(|(_, right_handed)| right_handed);
+                let s = sums
+                    .get(&key(i, j, right_handed))
+                    .copied()
+                    .unwrap_or_default();
+                let mut tangent = s - n * n.dot(&s);
+                if tangent.norm_squared() < 1e-12 {
+                    // no usable uv gradient, any vector perpendicular to the normal will do
+                    let axis = if n.x.abs() < 0.9 {
+                        Vector3::x()
+                    } else {
+                        Vector3::y()
+                    };
+                    tangent = axis - n * n.dot(&axis);
+                }
+                let tangent = tangent.normalize();
+                let w = if right_handed { 1.0 } else { -1.0 };
+                Vector4::new(tangent.x, tangent.y, tangent.z, w)
+            })
+        })
+        .collect()
+}
+
+// Smooth normals of a mesh without normals, the face normals around every vertex are
+// summed weighted by their area.
+fn compute_vertex_normals(
+    model: &tobj::Model,
+    positions: &[[Vector3<f32>; 3]],
+) -> Vec<[Vector3<f32>; 3]> {
+    let mesh = &model.mesh;
+    let mut sums = vec![Vector3::zeros(); mesh.positions.len() / 3];
+    for (i, p) in positions.iter().enumerate() {
+        let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+        for j in 0..3 {
+            sums[mesh.indices[3 * i + j] as usize] += n;
+        }
+    }
+    (0..positions.len())
+        .map(|i| {
+            std::array::from_fn(|j| {
+                sums[mesh.indices[3 * i + j] as usize]
+                    .try_normalize(1e-12)
+                    .unwrap_or(Vector3::y())
+            })
+        })
+        .collect()
+}
+
+// Texture maps are optional: without a diffuse map the albedo is the flat material color,
+// without a specular map there is no specular highlight and without a normal map the
+// interpolated vertex normals are used. Meshes without texture coordinates get (0, 0)
+// everywhere and meshes without normals get smooth normals computed from the faces.
 pub struct WModel {
     pub face_num: usize,
     pub model: tobj::Model,
-    pub texture: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-    pub texture_nm: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    pub texture: Option<Texture>,
+    pub texture_nm: Option<Texture>,
     pub tex_uv: Vec<[Vector2<f32>; 3]>, // tex_uv[face_index] = [[u1, v1], [u2, v2], [u3, v3]]
     pub faces: Vec<Vector3<usize>>, // faces[face_index] = [vertex_index1, vertex_index2, vertex_index3]
     pub normals: Vec<[Vector3<f32>; 3]>, // normals[face_index] = [[nx1, ny1, nz1], [nx2, ny2, nz2], [nx3, ny3, nz3]]
-    pub normals_tex: Vec<Vec<Vector3<f32>>>,
-    pub specular_tex: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-    pub light: Vector3<f32>,
+    pub normals_tex: Vec<Vec<Vec<Vector3<f32>>>>, // normals_tex[level][x][y] = decoded normal of a texel, empty without a normal map
+    pub normal_space: NormalMapSpace,
+    pub tangents: Vec<[Vector4<f32>; 3]>, // tangents[face_index] = tangent and handedness of each corner
+    pub specular_tex: Option<Texture>,
+    // optional inputs of the metallic-roughness shader, only the red channel is used
+    pub metallic_tex: Option<Texture>,
+    pub roughness_tex: Option<Texture>,
+    pub ao_tex: Option<Texture>,
+    // optional toon lighting ramp, sampled left to right by the diffuse intensity
+    pub ramp_tex: Option<Texture>,
+    pub material: Material,
+    pub sampler: Sampler, // used for every texture lookup of the model
 }
 
 impl WModel {
     pub fn new(
         model: tobj::Model,
-        texture: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-        specular_tex: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
-        texture_nm: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+        texture: Option<Texture>,
+        specular_tex: Option<Texture>,
+        texture_nm: Option<Texture>,
     ) -> Self {
         let face_num = model.mesh.indices.len() / 3;
         let mut tex_uv = vec![[Vector2::new(0.0, 0.0); 3]; face_num];
         let mut faces = vec![Vector3::new(0, 0, 0); face_num];
         let mut normals = vec![[Vector3::new(0.0, 0.0, 0.0); 3]; face_num];
-        let mut normals_tex = vec![
-            vec![Vector3::new(0.0, 0.0, 0.0); texture_nm.height() as usize];
-            texture_nm.width() as usize
-        ];
+
+        let has_uv = !model.mesh.texcoord_indices.is_empty();
+        let has_normals = !model.mesh.normal_indices.is_empty();
 
         for i in 0..face_num {
             faces[i] = Vector3::new(
@@ -84,31 +239,62 @@ impl WModel {
                 model.mesh.indices[i * 3 + 2] as usize,
             );
             for j in 0..3 {
-                tex_uv[i][j] = Vector2::new(
-                    model.mesh.texcoords[2 * model.mesh.texcoord_indices[3 * i + j] as usize],
-                    model.mesh.texcoords[2 * model.mesh.texcoord_indices[3 * i + j] as usize + 1],
-                );
-                normals[i][j] = Vector3::new(
-                    model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize],
-                    model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize + 1],
-                    model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize + 2],
-                );
+                if has_uv {
+                    tex_uv[i][j] = Vector2::new(
+                        model.mesh.texcoords[2 * model.mesh.texcoord_indices[3 * i + j] as usize],
+                        model.mesh.texcoords
+                            [2 * model.mesh.texcoord_indices[3 * i + j] as usize + 1],
+                    );
+                }
+                if has_normals {
+                    normals[i][j] = Vector3::new(
+                        model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize],
+                        model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize + 1],
+                        model.mesh.normals[3 * model.mesh.normal_indices[3 * i + j] as usize + 2],
+                    );
+                }
             }
         }
 
-        for x in 0..texture_nm.width() {
-            for y in 0..texture_nm.height() {
-                let color = texture_nm.get_pixel(x, y);
-                let normal = Vector3::new(
-                    2.0 * color[0] as f32 / 255.0 - 1.0,
-                    2.0 * color[1] as f32 / 255.0 - 1.0,
-                    2.0 * color[2] as f32 / 255.0 - 1.0,
-                );
-                normals_tex[x as usize][y as usize] = normal.normalize();
-            }
+        let positions: Vec<[Vector3<f32>; 3]> = faces
+            .iter()
+            .map(|f| {
+                f.map(|v| {
+                    Vector3::new(
+                        model.mesh.positions[v * 3],
+                        model.mesh.positions[v * 3 + 1],
+                        model.mesh.positions[v * 3 + 2],
+                    )
+                })
+                .into()
+            })
+            .collect();
+        if !has_normals {
+            normals = compute_vertex_normals(&model, &positions);
         }
+        let tangents = compute_tangents(&model, &positions, &tex_uv, &normals);
 
-        let light = LIGHT_DIR.normalize();
+        let normals_tex = texture_nm
+            .iter()
+            .flat_map(|tex| tex.levels.iter())
+            .map(|level| {
+                (0..level.width())
+                    .map(|x| {
+                        (0..level.height())
+                            .map(|y| {
+                                let color = level.get_pixel(x, y);
+                                let normal = Vector3::new(
+                                    2.0 * color[0] as f32 / 255.0 - 1.0,
+                                    2.0 * color[1] as f32 / 255.0 - 1.0,
+                                    2.0 * color[2] as f32 / 255.0 - 1.0,
+                                );
+                                normal.normalize()
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
 
         WModel {
             face_num,
@@ -120,7 +306,14 @@ impl WModel {
             normals,
             specular_tex,
             normals_tex,
-            light,
+            normal_space: NormalMapSpace::Object,
+            tangents,
+            metallic_tex: None,
+            roughness_tex: None,
+            ao_tex: None,
+            ramp_tex: None,
+            material: Material::default(),
+            sampler: Sampler::default(),
         }
     }
 
@@ -136,6 +329,19 @@ impl WModel {
         )
     }
 
+    // unique edges of the mesh as pairs of vertex indices
+    pub fn get_edges(&self) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = self
+            .faces
+            .iter()
+            .flat_map(|f| [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
     pub fn get_uv(&self, face_index: usize, vertex_index: usize) -> Vector2<f32> {
         self.tex_uv[face_index][vertex_index]
     }
@@ -144,34 +350,46 @@ impl WModel {
         self.tex_uv[face_index]
     }
 
+    // model space bounding box as (min, max)
+    pub fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+        for p in self.model.mesh.positions.chunks_exact(3) {
+            let p = Vector3::new(p[0], p[1], p[2]);
+            min = min.inf(&p);
+            max = max.sup(&p);
+        }
+        (min, max)
+    }
+
+    pub fn get_tangent(&self, face_index: usize, vertex_index: usize) -> Vector4<f32> {
+        self.tangents[face_index][vertex_index]
+    }
+
     pub fn get_face_normal(&self, face_index: usize) -> [Vector3<f32>; 3] {
         self.normals[face_index]
     }
 
+    // bake a model transform into the normals and tangents, tangent space normal texels are
+    // relative to the tangent frame and stay as they are
     pub fn trans_normals(&mut self, m: &Matrix4<f32>) {
-        let u: Matrix4<f32> = m.transpose().try_inverse().unwrap();
-        for f in self.normals.iter_mut() {
-            for n in f.iter_mut() {
-                let mut n_ = Vector4::new(n.x, n.y, n.z, 0.0);
-                n_ = u * n_;
-                *n = Vector3::new(n_.x, n_.y, n_.z).normalize();
-            }
+        let u = geometry::get_normal_matrix(m);
+        let transform = |m: &Matrix4<f32>, v: &Vector3<f32>| {
+            (m * v.push(0.0)).xyz().try_normalize(1e-12).unwrap_or(*v)
+        };
+        for n in self.normals.iter_mut().flatten() {
+            *n = transform(&u, n);
         }
-
-        for x in 0..self.texture_nm.width() {
-            for y in 0..self.texture_nm.height() {
-                let normal = self.normals_tex[x as usize][y as usize];
-                let mut n_ = Vector4::new(normal.x, normal.y, normal.z, 0.0);
-                n_ = u * n_;
-                self.normals_tex[x as usize][y as usize] =
-                    Vector3::new(n_.x, n_.y, n_.z).normalize();
+        if self.normal_space == NormalMapSpace::Object {
+            for n in self.normals_tex.iter_mut().flatten().flatten() {
+                *n = transform(&u, n);
             }
         }
-    }
-
-    pub fn trans_light(&mut self, m: &Matrix4<f32>) {
-        let light_dir = m * Vector4::new(self.light.x, self.light.y, self.light.z, 1.0);
-        self.light = Vector3::new(light_dir.x, light_dir.y, light_dir.z).normalize();
+        // tangents follow the surface, a mirroring transform flips their handedness
+        let mirror = m.fixed_view::<3, 3>(0, 0).determinant().signum();
+        for t in self.tangents.iter_mut().flatten() {
+            *t = transform(m, &t.xyz()).push(t.w * mirror);
+        }
     }
 
     pub fn get_normal(&self, face_index: usize, bc_screen: Vector3<f32>) -> Vector3<f32> {
@@ -184,10 +402,103 @@ impl WModel {
         normal.normalize()
     }
 
-    pub fn get_normal_tex(&self, uv: Vector2<f32>) -> Vector3<f32> {
-        let color = self.normals_tex[(uv.x * self.texture_nm.width() as f32) as usize]
-            [(uv.y * self.texture_nm.height() as f32) as usize];
-        let normal = Vector3::new(color[0], color[1], color[2]);
-        normal.normalize()
+    // `ddx` and `ddy` are the screen space derivatives of `uv`, they select the mip level
+    pub fn get_normal_tex(
+        &self,
+        uv: Vector2<f32>,
+        ddx: Vector2<f32>,
+        ddy: Vector2<f32>,
+    ) -> Option<Vector3<f32>> {
+        let texture_nm = self.texture_nm.as_ref()?;
+        let normal = self.sampler.sample_mip(
+            self.normals_tex.len(),
+            |l| texture_nm.levels[l].dimensions(),
+            uv,
+            ddx,
+            ddy,
+            |l, x, y| self.normals_tex[l][x as usize][y as usize],
+        );
+        Some(normal.normalize())
+    }
+
+    // RGBA of the diffuse texture, opaque white without one so that the material color is
+    // used as is
+    pub fn get_diffuse(
+        &self,
+        uv: Vector2<f32>,
+        ddx: Vector2<f32>,
+        ddy: Vector2<f32>,
+    ) -> ColorAlpha {
+        self.texture
+            .as_ref()
+            .map_or(ColorAlpha::repeat(1.0), |tex| {
+                self.sampler.sample_texture(tex, uv, ddx, ddy)
+            })
+    }
+
+    // specular intensity from the red channel of the specular texture, 0 without one
+    pub fn get_specular(&self, uv: Vector2<f32>, ddx: Vector2<f32>, ddy: Vector2<f32>) -> f32 {
+        self.specular_tex
+            .as_ref()
+            .map_or(0.0, |tex| self.sampler.sample_texture(tex, uv, ddx, ddy).x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two faces of the y = 0 plane sharing the edge from the origin to (0, 0, -1) and its uvs,
+    // the second maps u the other way around
+    fn mirrored_quad() -> WModel {
+        let mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0],
+            normals: vec![0.0, 1.0, 0.0],
+            texcoords: vec![0.5, 0.0, 1.0, 0.0, 0.5, 1.0],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            texcoord_indices: vec![0, 1, 2, 0, 2, 1],
+            normal_indices: vec![0; 6],
+            ..Default::default()
+        };
+        WModel::new(tobj::Model::new(mesh, "quad".to_string()), None, None, None)
+    }
+
+    #[test]
+    fn mirrored_uvs_split_tangents_by_handedness() {
+        let model = mirrored_quad();
+        for j in 0..3 {
+            assert_eq!(model.get_tangent(0, j), Vector4::new(1.0, 0.0, 0.0, 1.0));
+            assert_eq!(model.get_tangent(1, j), Vector4::new(-1.0, 0.0, 0.0, -1.0));
+        }
+    }
+
+    #[test]
+    fn trans_normals_moves_tangents_and_only_object_space_texels() {
+        let rotate = Matrix4::new_rotation(Vector3::z() * std::f32::consts::FRAC_PI_2);
+        let close = |a: Vector4<f32>, b: Vector4<f32>| (a - b).norm() < 1e-6;
+
+        let mut model = mirrored_quad();
+        model.normals_tex = vec![vec![vec![Vector3::y()]]];
+        model.normal_space = NormalMapSpace::Tangent;
+        model.trans_normals(&rotate);
+        assert!(close(model.get_face_normal(0)[0].push(0.0), -Vector4::x()));
+        assert!(close(
+            model.get_tangent(0, 0),
+            Vector4::new(0.0, 1.0, 0.0, 1.0)
+        ));
+        assert_eq!(model.normals_tex[0][0][0], Vector3::y());
+
+        let mut model = mirrored_quad();
+        model.normals_tex = vec![vec![vec![Vector3::y()]]];
+        model.normal_space = NormalMapSpace::Object;
+        model.trans_normals(&rotate);
+        assert!(close(model.normals_tex[0][0][0].push(0.0), -Vector4::x()));
+
+        let mut model = mirrored_quad();
+        model.trans_normals(&Matrix4::new_nonuniform_scaling(&Vector3::new(
+            -1.0, 1.0, 1.0,
+        )));
+        assert_eq!(model.get_tangent(0, 0), Vector4::new(-1.0, 0.0, 0.0, -1.0));
+        assert_eq!(model.get_tangent(1, 0), Vector4::new(1.0, 0.0, 0.0, 1.0));
     }
 }
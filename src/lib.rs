@@ -1,5 +1,15 @@
+pub mod clip;
 pub mod consts;
+pub mod framebuffer;
 pub mod geometry;
 pub mod img_io;
+pub mod line;
+pub mod material;
+pub mod outline;
 pub mod render;
+pub mod sampler;
+pub mod scene;
 pub mod shader;
+pub mod shadow;
+pub mod ssao;
+pub mod texture;
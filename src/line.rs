@@ -0,0 +1,131 @@
+use image::{ImageBuffer, Rgba};
+
+// Bresenham walk from (x0, y0) to (x1, y1), `plot` receives the pixel and
+// the position along the line in [0, 1]
+pub fn bresenham(x0: i32, y0: i32, x1: i32, y1: i32, mut plot: impl FnMut(i32, i32, f32)) {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let steps = dx.max(-dy).max(1) as f32;
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    let mut step = 0;
+    loop {
+        plot(x, y, step as f32 / steps);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        step += 1;
+    }
+}
+
+// Xiaolin Wu walk from (x0, y0) to (x1, y1) where integer coordinates are pixel centers,
+// `plot` receives the pixel, the position along the line in [0, 1] and the pixel coverage
+pub fn wu(x0: f32, y0: f32, x1: f32, y1: f32, mut plot: impl FnMut(i32, i32, f32, f32)) {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (mut x0, mut y0, mut x1, mut y1) = if steep {
+        (y0, x0, y1, x1)
+    } else {
+        (x0, y0, x1, y1)
+    };
+    let reversed = x0 > x1;
+    if reversed {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+    let dx = x1 - x0;
+    let gradient = if dx.abs() < f32::EPSILON {
+        1.0
+    } else {
+        (y1 - y0) / dx
+    };
+    let mut put = |x: i32, y: f32, c: f32| {
+        let t = if dx.abs() < f32::EPSILON {
+            0.0
+        } else {
+            ((x as f32 - x0) / dx).clamp(0.0, 1.0)
+        };
+        let t = if reversed { 1.0 - t } else { t };
+        let (yi, f) = (y.floor() as i32, y - y.floor());
+        if steep {
+            plot(yi, x, t, (1.0 - f) * c);
+            plot(yi + 1, x, t, f * c);
+        } else {
+            plot(x, yi, t, (1.0 - f) * c);
+            plot(x, yi + 1, t, f * c);
+        }
+    };
+
+    // end points are weighted by how much of their pixel the line covers
+    let xend0 = x0.round();
+    let xgap0 = 1.0 - (x0 + 0.5).fract();
+    put(xend0 as i32, y0 + gradient * (xend0 - x0), xgap0);
+    let xend1 = x1.round();
+    let xgap1 = (x1 + 0.5).fract();
+    put(xend1 as i32, y1 + gradient * (xend1 - x1), xgap1);
+
+    let mut intery = y0 + gradient * (xend0 - x0) + gradient;
+    for x in xend0 as i32 + 1..xend1 as i32 {
+        put(x, intery, 1.0);
+        intery += gradient;
+    }
+}
+
+pub fn line(
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    color: Rgba<u8>,
+) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    bresenham(x0, y0, x1, y1, |x, y, _| {
+        if x >= 0 && y >= 0 && x < width && y < height {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    });
+}
+
+// anti-aliased line, integer coordinates are pixel centers
+pub fn line_aa(
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    color: Rgba<u8>,
+) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    wu(x0, y0, x1, y1, |x, y, _, coverage| {
+        if x >= 0 && y >= 0 && x < width && y < height {
+            blend_pixel(image, x as u32, y as u32, color, coverage);
+        }
+    });
+}
+
+// mix `color` into the pixel with weight `alpha` times the alpha of `color`
+pub fn blend_pixel(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    x: u32,
+    y: u32,
+    color: Rgba<u8>,
+    alpha: f32,
+) {
+    let alpha = (alpha * color[3] as f32 / 255.0).clamp(0.0, 1.0);
+    let pixel = image.get_pixel_mut(x, y);
+    for i in 0..3 {
+        pixel[i] = (pixel[i] as f32 * (1.0 - alpha) + color[i] as f32 * alpha).round() as u8;
+    }
+    pixel[3] = (pixel[3] as f32 * (1.0 - alpha) + 255.0 * alpha).round() as u8;
+}
@@ -1,32 +1,94 @@
-mod consts;
-mod geometry;
-mod img_io;
-mod render;
-mod shader;
+use image::{ImageBuffer, Rgba};
+use nalgebra::Vector3;
+use std::{io, process, time};
 
-use image::{ImageBuffer, Rgb};
-use std::time;
-
-use consts::*;
-use img_io::*;
-use render::*;
+use lib::consts::*;
+use lib::img_io::{self, *};
+use lib::material::Material;
+use lib::render::*;
+use lib::scene::{Camera, Light, Scene, Transform};
+use lib::texture::{ColorSpace, Texture};
 
 static WIDTH: u32 = 1600;
 static HEIGHT: u32 = 1600;
 
+// a map whose file does not exist is left out, any other failure stops the program
+fn load_map(filename: &str, color_space: ColorSpace) -> Option<Texture> {
+    match img_io::load_image(filename, color_space) {
+        Ok(texture) => Some(texture),
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::NotFound) =>
+        {
+            None
+        }
+        Err(e) => {
+            eprintln!("failed to load {filename}: {e}");
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let now = time::Instant::now();
-    let mut image: ImageBuffer<Rgb<u8>, Vec<u8>> = init_image(WIDTH, HEIGHT);
+    let mut image: ImageBuffer<Rgba<u8>, Vec<u8>> = init_image(WIDTH, HEIGHT);
     let model = img_io::load_obj(AFRICAN_HEAD_OBJ);
-    let texture = img_io::load_image(AFRICAN_HEAD_DIFFUSE).unwrap();
-    let specture_tex = img_io::load_image(AFRICAN_HEAD_SPECULAR).unwrap();
-    let normal_tex = img_io::load_image(AFRICAN_HEAD_NORMAL).unwrap();
-    // let model = img_io::load_obj(DIABLO3_OBJ);
-    // let texture = img_io::load_image(DIABLO3_DIFFUSE).unwrap();
-    // let specture_tex = img_io::load_image(DIABLO3_SPECTURE).unwrap();
-    // let normal_tex = img_io::load_image(DIABLO3_NORMAL).unwrap();
-    let mut model = WModel::new(model, texture, specture_tex, normal_tex);
-    render_obj(&mut model, &mut image);
-    img_io::output_image("output.png", &mut image);
+    let texture = load_map(AFRICAN_HEAD_DIFFUSE, ColorSpace::Srgb);
+    let specture_tex = load_map(AFRICAN_HEAD_SPECULAR, ColorSpace::Linear);
+    let normal_tex = load_map(AFRICAN_HEAD_NORMAL, ColorSpace::Linear);
+    let head = WModel::new(model, texture, specture_tex, normal_tex);
+
+    let model = img_io::load_obj(DIABLO3_OBJ);
+    let texture = load_map(DIABLO3_DIFFUSE, ColorSpace::Srgb);
+    let specture_tex = load_map(DIABLO3_SPECTURE, ColorSpace::Linear);
+    let normal_tex = load_map(DIABLO3_NORMAL, ColorSpace::Linear);
+    let mut diablo = WModel::new(model, texture, specture_tex, normal_tex);
+    diablo.material = Material::pbr(Vector3::new(1.0, 1.0, 1.0), 0.0, 0.6);
+
+    let floor = WModel::new(
+        make_plane(),
+        Some(Texture::new(
+            fill_image(1, 1, Rgba([180, 180, 180, 255])),
+            ColorSpace::Srgb,
+        )),
+        None,
+        None,
+    );
+
+    let camera = Camera::new(
+        Vector3::new(1.0, 1.0, 2.5),
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        std::f32::consts::FRAC_PI_4,
+        WIDTH as f32 / HEIGHT as f32,
+    );
+    let mut scene = Scene::new(camera);
+    scene.add_light(Light::new(Vector3::new(0.0, 3.0, 3.0)));
+    scene.add(
+        &head,
+        Transform::new(
+            Vector3::new(-0.5, -0.1, 0.0),
+            Vector3::zeros(),
+            Vector3::new(0.4, 0.4, 0.4),
+        ),
+    );
+    scene.add(
+        &diablo,
+        Transform::new(
+            Vector3::new(0.4, 0.0, 0.0),
+            Vector3::new(0.0, -0.3, 0.0),
+            Vector3::new(0.5, 0.5, 0.5),
+        ),
+    );
+    scene.add(
+        &floor,
+        Transform::new(
+            Vector3::new(0.0, -0.5, 0.0),
+            Vector3::zeros(),
+            Vector3::new(1.0, 1.0, 1.0),
+        ),
+    );
+    render_scene(&scene, &mut image, &RenderConfig::default());
+    img_io::output_image("output.png", &image);
     println!("{:?}", now.elapsed());
 }
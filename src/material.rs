@@ -0,0 +1,94 @@
+use crate::framebuffer::{BlendMode, Color};
+
+// lighting model used to shade a model in the main pass
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadingModel {
+    BlinnPhong,
+    MetallicRoughness, // Cook-Torrance GGX, see `PbrShader`
+    Toon,              // flat bands of light, see `ToonShader`
+}
+
+// surface parameters of a model, modulated per texel by the model's textures in the shaders
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub shading: ShadingModel,
+    pub ambient: Color, // multiplied by the diffuse texture
+    pub diffuse: Color, // also multiplied by it, the base color for metallic-roughness
+    pub specular: Color,
+    pub ambient_strength: f32,
+    pub diffuse_strength: f32,
+    pub specular_strength: f32, // scaled by the red channel of the specular texture
+    pub shininess: f32,         // Blinn-Phong exponent, higher gives smaller highlights
+    pub metallic: f32,          // 0 for dielectrics, 1 for metals
+    pub roughness: f32,         // perceptual roughness in [0, 1]
+    pub bands: u32,             // toon lighting levels, when the model has no ramp texture
+    pub opacity: f32,           // multiplied by the alpha of the diffuse texture
+    // None draws the model opaque, otherwise it is blended over the opaque models after they
+    // are drawn
+    pub blend: Option<BlendMode>,
+    pub alpha_cutoff: Option<f32>, // fragments with a lower alpha are discarded, for cutouts
+}
+
+impl Material {
+    pub fn new(ambient: Color, diffuse: Color, specular: Color, shininess: f32) -> Self {
+        Material {
+            shading: ShadingModel::BlinnPhong,
+            ambient,
+            diffuse,
+            specular,
+            ambient_strength: 1.0,
+            diffuse_strength: 1.0,
+            specular_strength: 1.0,
+            shininess,
+            metallic: 0.0,
+            roughness: 0.5,
+            bands: 3,
+            opacity: 1.0,
+            blend: None,
+            alpha_cutoff: None,
+        }
+    }
+
+    // cel shaded material with `bands` lighting levels
+    pub fn toon(diffuse: Color, bands: u32) -> Self {
+        Material {
+            shading: ShadingModel::Toon,
+            diffuse,
+            ambient_strength: 0.2,
+            bands,
+            ..Default::default()
+        }
+    }
+
+    // metallic-roughness material with a flat base color
+    pub fn pbr(base_color: Color, metallic: f32, roughness: f32) -> Self {
+        Material {
+            shading: ShadingModel::MetallicRoughness,
+            diffuse: base_color,
+            metallic,
+            roughness,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            shading: ShadingModel::BlinnPhong,
+            ambient: Color::new(1.0, 1.0, 1.0),
+            diffuse: Color::new(1.0, 1.0, 1.0),
+            specular: Color::new(1.0, 1.0, 1.0),
+            ambient_strength: 0.05,
+            diffuse_strength: 1.0,
+            specular_strength: 0.6,
+            shininess: 32.0,
+            metallic: 0.0,
+            roughness: 0.5,
+            bands: 3,
+            opacity: 1.0,
+            blend: None,
+            alpha_cutoff: None,
+        }
+    }
+}
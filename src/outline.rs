@@ -0,0 +1,97 @@
+use nalgebra::Matrix4;
+
+use crate::framebuffer::{Color, FrameBuffer};
+
+// Lines drawn over silhouette and crease edges found in the z buffer of the main pass and
+// the normals reconstructed from it.
+#[derive(Clone, Copy, Debug)]
+pub struct Outline {
+    pub color: Color,
+    pub width: u32,           // line width in pixels, 0 draws nothing
+    pub depth_threshold: f32, // view depth jump relative to the distance that counts as an edge
+    pub crease_angle: f32,    // angle in radians between neighboring normals that counts as an edge
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Outline {
+            color: Color::zeros(),
+            width: 2,
+            depth_threshold: 0.02,
+            crease_angle: 1.0,
+        }
+    }
+}
+
+impl Outline {
+    // Draw the outlines into every sample of `fb`. `projection` and `viewport` are the
+    // matrices the frame buffer was rendered with.
+    pub fn apply(&self, fb: &mut FrameBuffer, projection: &Matrix4<f32>, viewport: &Matrix4<f32>) {
+        if self.width == 0 {
+            return;
+        }
+        let (width, height) = (fb.width as usize, fb.height as usize);
+        let Some(inv_screen) = (viewport * projection).try_inverse() else {
+            return;
+        };
+        let positions = fb.view_positions(&inv_screen);
+        let normals = fb.view_normals(&positions);
+        let cos_crease = self.crease_angle.cos();
+
+        // compare every pixel with its right and upper neighbor, a silhouette or depth edge
+        // is drawn on the nearer side, a crease on the pixel itself
+        let mut edges = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let i = x + y * width;
+                for j in [
+                    (x + 1 < width).then(|| i + 1),
+                    (y + 1 < height).then(|| i + width),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    match (positions[i], positions[j]) {
+                        (Some(_), None) => edges[i] = true,
+                        (None, Some(_)) => edges[j] = true,
+                        (Some(p), Some(q)) => {
+                            if (p.z - q.z).abs() > self.depth_threshold * p.z.abs().min(q.z.abs()) {
+                                edges[if p.z > q.z { i } else { j }] = true;
+                            } else if let (Some(n), Some(m)) = (normals[i], normals[j]) {
+                                if n.dot(&m) < cos_crease {
+                                    edges[i] = true;
+                                }
+                            }
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+
+        // widen every edge pixel to a disc of `width` pixels
+        let lo = -(self.width as i32 / 2);
+        let hi = (self.width as i32 - 1) / 2;
+        let center = (lo + hi) as f32 / 2.0;
+        let radius2 = (self.width as f32 / 2.0).powi(2);
+        let mut lines = vec![false; width * height];
+        for (i, _) in edges.iter().enumerate().filter(|(_, &e)| e) {
+            let (x, y) = ((i % width) as i32, (i / width) as i32);
+            for dy in lo..=hi {
+                for dx in lo..=hi {
+                    let (px, py) = (x + dx, y + dy);
+                    let d2 = (dx as f32 - center).powi(2) + (dy as f32 - center).powi(2);
+                    if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 || d2 > radius2
+                    {
+                        continue;
+                    }
+                    lines[px as usize + py as usize * width] = true;
+                }
+            }
+        }
+
+        for (i, _) in lines.iter().enumerate().filter(|(_, &l)| l) {
+            fb.color[i * fb.samples..(i + 1) * fb.samples].fill(self.color.push(1.0));
+        }
+    }
+}
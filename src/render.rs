@@ -1,142 +1,915 @@
-use image::{ImageBuffer, Rgb};
-use nalgebra::{Matrix4, Vector2, Vector3};
+use image::{ImageBuffer, Rgba};
+use nalgebra::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 use crate::{
-    consts::{ASPECT, CAMERA, FOVY, LIGHT},
+    clip::{clip_line, clip_triangle},
+    framebuffer::{BlendMode, ColorAlpha, FrameBuffer, Msaa, Tile, ToneMapping},
     geometry,
-    img_io::{output_image, WModel},
-    shader::{DepthShader, GouphShader, Shader},
+    img_io::WModel,
+    line::{blend_pixel, bresenham, wu},
+    material::ShadingModel,
+    outline::Outline,
+    scene::{Instance, Light, Scene},
+    shader::{
+        BlinnPhongShader, DepthShader, PbrShader, Shader, SurfaceInputs, ToonShader, Varying,
+    },
+    shadow::{ShadowMap, ShadowSettings},
+    ssao::Ssao,
 };
 
-// calculate barycentric coordinates
-fn barycentric(pts: &[Vector3<f32>; 3], p: Vector2<f32>) -> Vector3<f32> {
-    let u = Vector3::new(pts[2].x - pts[0].x, pts[1].x - pts[0].x, pts[0].x - p.x);
-    let v = Vector3::new(pts[2].y - pts[0].y, pts[1].y - pts[0].y, pts[0].y - p.y);
-    let cross = u.cross(&v);
-    if cross.z.abs() < 1.0 {
-        return Vector3::new(-1.0, 1.0, 1.0);
-    }
-    Vector3::new(
-        1.0 - (cross.x + cross.y) / cross.z,
-        cross.y / cross.z,
-        cross.x / cross.z,
-    )
+// which faces are skipped, decided by their winding on screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMode {
+    None,
+    Back,  // skip faces wound clockwise on screen
+    Front, // skip faces wound counter-clockwise on screen
 }
 
-pub fn triangle(
-    model: &WModel,
+impl CullMode {
+    // `area` is the doubled signed area of the triangle on screen
+    fn culls(&self, area: f32) -> bool {
+        match self {
+            CullMode::None => false,
+            CullMode::Back => area <= 0.0,
+            CullMode::Front => area >= 0.0,
+        }
+    }
+}
+
+// order of the faces of transparent models, the models themselves are always drawn back to front
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransparencySort {
+    PerObject, // faces of a model keep their order
+    PerFace,   // faces of a model are drawn back to front as well
+}
+
+pub struct RenderConfig {
+    pub threads: usize, // 1 renders the whole image on the calling thread
+    pub tile_size: u32, // edge length of the square screen tiles in pixels
+    pub cull_mode: CullMode,
+    pub shadow_cull_mode: CullMode, // Front keeps back faces in the shadow buffer against acne
+    pub msaa: Msaa,
+    pub tone_mapping: ToneMapping,
+    pub exposure: f32,
+    pub shadows: ShadowSettings,
+    pub ssao: Option<Ssao>, // ambient occlusion multiplied into the main pass
+    pub outline: Option<Outline>,
+    pub transparency_sort: TransparencySort,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 64,
+            cull_mode: CullMode::Back,
+            shadow_cull_mode: CullMode::None,
+            msaa: Msaa::Off,
+            tone_mapping: ToneMapping::Clamp,
+            exposure: 1.0,
+            shadows: ShadowSettings::default(),
+            ssao: None,
+            outline: None,
+            transparency_sort: TransparencySort::PerObject,
+        }
+    }
+}
+
+// triangle after clipping and viewport transform, ready to be rasterized
+pub struct ScreenTriangle<V> {
+    pub pts: [Vector4<f32>; 3],  // screen position in xyz, clip space w in w
+    pub bars: [Vector3<f32>; 3], // weights of each vertex relative to the original face
+    pub varyings: [V; 3],        // vertex shader outputs at the corners of the original face
+}
+
+impl<V> ScreenTriangle<V> {
+    // vertex positions snapped to the sub-pixel grid
+    fn snapped(&self) -> [(i64, i64); 3] {
+        self.pts.map(|p| (snap(p.x), snap(p.y)))
+    }
+
+    // mean screen depth of the corners, larger is closer to the viewer
+    fn depth(&self) -> f32 {
+        self.pts.iter().map(|p| p.z).sum::<f32>() / 3.0
+    }
+
+    // pixel range touched by the rasterizer, the upper bound is exclusive.
+    // `margin` widens it for sample positions away from the pixel center.
+    fn bbox(&self, margin: i32) -> (Vector2<i32>, Vector2<i32>) {
+        let v = self.snapped();
+        let half = SUBPIXEL / 2;
+        let xmin = v.iter().map(|p| p.0).min().unwrap() - half;
+        let ymin = v.iter().map(|p| p.1).min().unwrap() - half;
+        let xmax = v.iter().map(|p| p.0).max().unwrap() - half;
+        let ymax = v.iter().map(|p| p.1).max().unwrap() - half;
+        (
+            Vector2::new(
+                (xmin >> SUBPIXEL_BITS) as i32 - margin,
+                (ymin >> SUBPIXEL_BITS) as i32 - margin,
+            ),
+            Vector2::new(
+                (xmax >> SUBPIXEL_BITS) as i32 + 1 + margin,
+                (ymax >> SUBPIXEL_BITS) as i32 + 1 + margin,
+            ),
+        )
+    }
+}
+
+// vertices are snapped to 1/256 of a pixel before rasterization
+const SUBPIXEL_BITS: i64 = 8;
+const SUBPIXEL: i64 = 1 << SUBPIXEL_BITS;
+
+fn snap(v: f32) -> i64 {
+    (v * SUBPIXEL as f32).round() as i64
+}
+
+// Edge function a * x + b * y + c of the edge from v0 to v1 in sub-pixel units,
+// positive on the left of the edge. Samples exactly on the edge belong to it only when
+// it is a top or a left edge, so pixels on an edge shared by two triangles are drawn once.
+struct Edge {
+    a: i64,
+    b: i64,
+    c: i64,
+    bias: i64,
+}
+
+impl Edge {
+    fn new(v0: (i64, i64), v1: (i64, i64)) -> Self {
+        let a = v0.1 - v1.1;
+        let b = v1.0 - v0.0;
+        let c = -(a * v0.0 + b * v0.1);
+        // y grows upwards, so in a counter-clockwise triangle a top edge runs
+        // to the left and a left edge runs downwards
+        let top_left = (a == 0 && b < 0) || a > 0;
+        Edge {
+            a,
+            b,
+            c,
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+
+    fn eval(&self, x: i64, y: i64) -> i64 {
+        self.a * x + self.b * y + self.c
+    }
+}
+
+// rasterize a triangle into the tile, testing coverage and depth at every sample in
+// `offsets` around the pixel center. A fragment discarded by the shader leaves both the
+// color and the depth untouched.
+pub fn triangle<S: Shader>(
+    tri: &ScreenTriangle<S::Varyings>,
+    tile: &mut Tile,
+    offsets: &[(f32, f32)],
+    shader: &S,
+    blend: Option<BlendMode>,
+) {
+    let v = tri.snapped();
+    let area = Edge::new(v[0], v[1]).eval(v[2].0, v[2].1);
+    if area == 0 {
+        return;
+    }
+    // walk the vertices counter-clockwise so that inside means positive edge functions
+    let order = if area > 0 { [0, 1, 2] } else { [0, 2, 1] };
+    let area = area.abs() as f32;
+    let edges = [
+        Edge::new(v[order[1]], v[order[2]]),
+        Edge::new(v[order[2]], v[order[0]]),
+        Edge::new(v[order[0]], v[order[1]]),
+    ];
+
+    let bar_conv = Matrix3::from_columns(&order.map(|i| tri.bars[i]));
+    let z = Vector3::from(order.map(|i| tri.pts[i].z));
+    let w_recip = Vector3::from(order.map(|i| 1.0 / tri.pts[i].w));
+    let samples: Vec<(i64, i64)> = offsets
+        .iter()
+        .map(|(ox, oy)| (SUBPIXEL / 2 + snap(*ox), SUBPIXEL / 2 + snap(*oy)))
+        .collect();
+
+    // perspective-correct weights of the original face at a sub-pixel position, None
+    // where the plane of the triangle lies behind the camera
+    let bar_at = |sx: i64, sy: i64| {
+        let w = edges.each_ref().map(|e| e.eval(sx, sy) as f32);
+        let bc_clip = (Vector3::from(w) / area).component_mul(&w_recip);
+        let w_sum = bc_clip.sum();
+        (w_sum > 1e-12).then(|| bar_conv * (bc_clip / w_sum))
+    };
+    let center = |p: i32| p as i64 * SUBPIXEL + SUBPIXEL / 2;
+
+    let (bboxmin, bboxmax) = tri.bbox((offsets.len() > 1) as i32);
+    let xmin = bboxmin.x.max(tile.x0);
+    let ymin = bboxmin.y.max(tile.y0);
+    let xmax = bboxmax.x.min(tile.x0 + tile.width);
+    let ymax = bboxmax.y.min(tile.y0 + tile.height);
+
+    for y in ymin..ymax {
+        for x in xmin..xmax {
+            let idx = ((x - tile.x0) + (y - tile.y0) * tile.width) as usize * tile.samples;
+            // depth writes wait for the fragment shader, which may discard the fragment
+            let mut covered = 0u32;
+            let mut depths = [0.0f32; 32];
+            let mut shade_bc = None;
+            for (s, (ox, oy)) in samples.iter().enumerate() {
+                let (sx, sy) = ((x as i64) * SUBPIXEL + ox, (y as i64) * SUBPIXEL + oy);
+                let w = edges.each_ref().map(|e| e.eval(sx, sy));
+                if (0..3).any(|i| w[i] + edges[i].bias < 0) {
+                    continue;
+                }
+
+                let bc = Vector3::new(w[0] as f32, w[1] as f32, w[2] as f32) / area;
+                let z: f32 = z.dot(&bc);
+
+                if tile.depth[idx + s] < z {
+                    depths[s] = z;
+                    covered |= 1 << s;
+                    shade_bc.get_or_insert(bc);
+                }
+            }
+
+            // shade once per pixel at the first covered sample, with varyings interpolated
+            // perspective-correct over the original face
+            if let Some(bc_screen) = shade_bc {
+                let bc_clip = bc_screen.component_mul(&w_recip);
+                let bar = bar_conv * (bc_clip / bc_clip.sum());
+                let varyings = S::Varyings::interpolate(&tri.varyings, bar);
+                // derivatives are differences between the pixel centers of the 2x2 quad
+                // holding the pixel, extrapolated across the plane where the quad leaves it
+                let (qx, qy) = (x & !1, y & !1);
+                let bar00 = bar_at(center(qx), center(qy));
+                let diff = |b: Option<Vector3<f32>>| match (bar00, b) {
+                    (Some(b00), Some(b)) => b - b00,
+                    _ => Vector3::zeros(),
+                };
+                let ddx = S::Varyings::interpolate(
+                    &tri.varyings,
+                    diff(bar_at(center(qx + 1), center(qy))),
+                );
+                let ddy = S::Varyings::interpolate(
+                    &tri.varyings,
+                    diff(bar_at(center(qx), center(qy + 1))),
+                );
+                let mut color = ColorAlpha::zeros();
+                if shader.fragment(&varyings, &ddx, &ddy, &mut color) {
+                    continue;
+                }
+                for (s, &z) in depths.iter().enumerate().take(tile.samples) {
+                    if covered & (1 << s) == 0 {
+                        continue;
+                    }
+                    // blended fragments test depth but don't write it, so that everything
+                    // behind them still shows through
+                    match blend {
+                        Some(blend) => {
+                            tile.color[idx + s] = blend.apply(color, tile.color[idx + s]);
+                        }
+                        // opaque fragments drop their alpha, which only decides the material's
+                        // alpha cutoff in the shader
+                        None => {
+                            tile.depth[idx + s] = z;
+                            tile.color[idx + s] = color.xyz().push(1.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// run the vertex shader on a face, clip it against the view frustum, cull it by its
+// winding and append what is left to `out` as screen space triangles
+pub fn setup_face<S: Shader>(
     face_index: usize,
-    pts: &[Vector3<f32>; 3],
-    image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
-    z_buffer: &mut [f32],
-    shader: &mut dyn Shader,
+    shader: &S,
+    viewport: &Matrix4<f32>,
+    cull_mode: CullMode,
+    out: &mut Vec<ScreenTriangle<S::Varyings>>,
 ) {
-    let triangle_tex_coords = model.get_face_uv(face_index);
+    let corners: [_; 3] = std::array::from_fn(|j| shader.vertex(face_index, j));
+    let clip_coords = corners.map(|c| c.0);
+    let varyings = corners.map(|c| c.1);
+    let polygon = clip_triangle(&clip_coords);
+    let screen_coords: Vec<Vector4<f32>> = polygon
+        .iter()
+        .map(|v| {
+            let p = viewport * (v.pos / v.pos.w);
+            Vector4::new(p.x, p.y, p.z, v.pos.w)
+        })
+        .collect();
 
-    let mut bboxmin = Vector2::new((image.width() - 1) as i32, (image.height() - 1) as i32);
-    let mut bboxmax = Vector2::new(0, 0);
-    let clamp = Vector2::new(image.width() as i32 - 1, image.height() as i32 - 1);
+    for k in 1..polygon.len().saturating_sub(1) {
+        let (a, b, c) = (screen_coords[0], screen_coords[k], screen_coords[k + 1]);
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if cull_mode.culls(area) {
+            continue;
+        }
+        out.push(ScreenTriangle {
+            pts: [screen_coords[0], screen_coords[k], screen_coords[k + 1]],
+            bars: [polygon[0].bar, polygon[k].bar, polygon[k + 1].bar],
+            varyings,
+        });
+    }
+}
 
-    for p in pts.iter().take(3) {
-        bboxmin.x = std::cmp::max(0, bboxmin.x.min((p.x + 1.0) as i32));
-        bboxmin.y = std::cmp::max(0, bboxmin.y.min((p.y + 1.0) as i32));
-        bboxmax.x = std::cmp::min(clamp.x, bboxmax.x.max((p.x + 1.0) as i32));
-        bboxmax.y = std::cmp::min(clamp.y, bboxmax.y.max((p.y + 1.0) as i32));
+// Rasterize every face of the model. With more than one thread the triangles are binned
+// into screen tiles which are shaded in parallel. Triangles keep their submission order
+// inside every tile, so the result is identical to the single threaded path. Blended
+// triangles are sorted back to front first when `config` asks for sorting per face.
+pub fn draw_model<S: Shader>(
+    model: &WModel,
+    shader: &S,
+    viewport: &Matrix4<f32>,
+    fb: &mut FrameBuffer,
+    cull_mode: CullMode,
+    blend: Option<BlendMode>,
+    config: &RenderConfig,
+) {
+    let mut triangles = Vec::with_capacity(model.face_num);
+    for i in 0..model.face_num {
+        setup_face(i, shader, viewport, cull_mode, &mut triangles);
+    }
+    if blend.is_some() && config.transparency_sort == TransparencySort::PerFace {
+        triangles.sort_by(|a, b| a.depth().total_cmp(&b.depth()));
     }
 
-    for x in bboxmin.x..bboxmax.x {
-        for y in bboxmin.y..bboxmax.y {
-            let bc_screen = barycentric(pts, Vector2::new(x as f32, y as f32));
+    let offsets = match fb.samples {
+        1 => Msaa::Off.offsets(),
+        _ => config.msaa.offsets(),
+    };
+    let margin = (offsets.len() > 1) as i32;
+    let (width, height) = (fb.width as i32, fb.height as i32);
+    if config.threads <= 1 {
+        let mut tile = Tile::new(0, 0, width, height, fb);
+        for tri in triangles.iter() {
+            triangle(tri, &mut tile, offsets, shader, blend);
+        }
+        tile.write_back(fb);
+        return;
+    }
 
-            if bc_screen.x < 0.0 || bc_screen.y < 0.0 || bc_screen.z < 0.0 {
-                continue;
+    let tile_size = config.tile_size.max(1) as i32;
+    let tiles_x = (width + tile_size - 1) / tile_size;
+    let tiles_y = (height + tile_size - 1) / tile_size;
+    let mut bins: Vec<Vec<usize>> = vec![Vec::new(); (tiles_x * tiles_y) as usize];
+    for (i, tri) in triangles.iter().enumerate() {
+        let (bboxmin, bboxmax) = tri.bbox(margin);
+        let (bboxmin, bboxmax) = (
+            bboxmin.sup(&Vector2::zeros()),
+            bboxmax.inf(&Vector2::new(width, height)),
+        );
+        if bboxmin.x >= bboxmax.x || bboxmin.y >= bboxmax.y {
+            continue;
+        }
+        for ty in bboxmin.y / tile_size..=(bboxmax.y - 1) / tile_size {
+            for tx in bboxmin.x / tile_size..=(bboxmax.x - 1) / tile_size {
+                bins[(tx + ty * tiles_x) as usize].push(i);
             }
+        }
+    }
 
-            let z: f32 = pts[0].z * bc_screen.x + pts[1].z * bc_screen.y + pts[2].z * bc_screen.z;
-
-            if z_buffer[(x + y * image.width() as i32) as usize] < z {
-                z_buffer[(x + y * image.width() as i32) as usize] = z;
-                let uv = Vector2::new(
-                    triangle_tex_coords[0].x * bc_screen.x
-                        + triangle_tex_coords[1].x * bc_screen.y
-                        + triangle_tex_coords[2].x * bc_screen.z,
-                    triangle_tex_coords[0].y * bc_screen.x
-                        + triangle_tex_coords[1].y * bc_screen.y
-                        + triangle_tex_coords[2].y * bc_screen.z,
-                );
+    let next_tile = AtomicUsize::new(0);
+    let tiles: Vec<Tile> = thread::scope(|s| {
+        let workers: Vec<_> = (0..config.threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let t = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if t >= bins.len() {
+                            break;
+                        }
+                        if bins[t].is_empty() {
+                            continue;
+                        }
+                        let (tx, ty) = (t as i32 % tiles_x, t as i32 / tiles_x);
+                        let x0 = tx * tile_size;
+                        let y0 = ty * tile_size;
+                        let mut tile = Tile::new(
+                            x0,
+                            y0,
+                            tile_size.min(width - x0),
+                            tile_size.min(height - y0),
+                            fb,
+                        );
+                        for &i in bins[t].iter() {
+                            triangle(&triangles[i], &mut tile, offsets, shader, blend);
+                        }
+                        done.push(tile);
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+
+    for tile in tiles.iter() {
+        tile.write_back(fb);
+    }
+}
+
+// draw a model in the main pass with the cull mode of `config` and the blend mode of its material
+fn draw_with<S: Shader>(
+    shader: &S,
+    model: &WModel,
+    viewport: &Matrix4<f32>,
+    fb: &mut FrameBuffer,
+    config: &RenderConfig,
+) {
+    let blend = model.material.blend;
+    draw_model(model, shader, viewport, fb, config.cull_mode, blend, config);
+}
+
+// viewport used by every pass, leaves a border of 1/8 of the image on each side
+fn screen_viewport(width: u32, height: u32) -> Matrix4<f32> {
+    geometry::get_viewport(
+        width as f32 / 8.0,
+        height as f32 / 8.0,
+        width as f32 * 3.0 / 4.0,
+        height as f32 * 3.0 / 4.0,
+    )
+}
+
+// Render every instance of the scene. All instances share one shadow map and one z buffer.
+// Opaque instances are drawn first, then the transparent ones back to front without writing
+// depth. Transparent instances still cast full shadows.
+pub fn render_scene(
+    scene: &Scene,
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    config: &RenderConfig,
+) {
+    let viewport = screen_viewport(image.width(), image.height());
 
-                let color = model.texture.get_pixel(
-                    (uv.x * model.texture.width() as f32) as u32,
-                    (uv.y * model.texture.height() as f32) as u32,
+    // render shadow map for the first light
+    let shadow_map = scene.lights.first().map(|light| {
+        let mut shadow_map = ShadowMap::new(light, scene.bounds(), config.shadows.resolution);
+        let mut shadow_fb = FrameBuffer::new(shadow_map.width, shadow_map.height, 1);
+        let light_conv = shadow_map.light_conv();
+        for instance in scene.instances.iter() {
+            let shader = DepthShader::new(light_conv * instance.transform.matrix(), instance.model);
+            draw_model(
+                instance.model,
+                &shader,
+                &shadow_map.viewport,
+                &mut shadow_fb,
+                config.shadow_cull_mode,
+                None,
+                config,
+            );
+        }
+        shadow_map.depth = shadow_fb.depth;
+        shadow_map
+    });
+
+    // render frame buffer
+    {
+        let lookat = scene.camera.view();
+        let projection = scene.camera.projection();
+        let lights: Vec<Light> = scene
+            .lights
+            .iter()
+            .map(|l| l.transformed(&lookat))
+            .collect();
+
+        let mut fb = FrameBuffer::from_image(image, config.msaa.samples());
+        let draw = |instance: &Instance, fb: &mut FrameBuffer| {
+            let model_m = instance.transform.matrix();
+            let trans_shadow = shadow_map
+                .as_ref()
+                .map_or(Matrix4::identity(), |map| map.transform() * model_m);
+            let inputs = SurfaceInputs::new(
+                projection,
+                lookat * model_m,
+                instance.model,
+                trans_shadow,
+                &lights,
+                shadow_map.as_ref(),
+                config.shadows,
+            );
+            let model = instance.model;
+            match model.material.shading {
+                ShadingModel::BlinnPhong => {
+                    draw_with(&BlinnPhongShader::new(inputs), model, &viewport, fb, config)
+                }
+                ShadingModel::Toon => {
+                    draw_with(&ToonShader::new(inputs), model, &viewport, fb, config)
+                }
+                ShadingModel::MetallicRoughness => {
+                    draw_with(&PbrShader::new(inputs), model, &viewport, fb, config)
+                }
+            }
+        };
+
+        let (opaque, mut transparent): (Vec<&Instance>, Vec<&Instance>) = scene
+            .instances
+            .iter()
+            .partition(|instance| instance.model.material.blend.is_none());
+        for instance in opaque {
+            draw(instance, &mut fb);
+        }
+        if let Some(ssao) = &config.ssao {
+            ssao.apply(&mut fb, &projection, &viewport, config.threads);
+        }
+
+        // back to front by the view depth of the center of the bounds, the camera looks along -z
+        let view_depth = |instance: &Instance| {
+            let (min, max) = instance.model.bounds();
+            let center = ((min + max) / 2.0).push(1.0);
+            (lookat * instance.transform.matrix() * center).z
+        };
+        transparent.sort_by(|a, b| view_depth(a).total_cmp(&view_depth(b)));
+        for instance in transparent {
+            draw(instance, &mut fb);
+        }
+        if let Some(outline) = &config.outline {
+            outline.apply(&mut fb, &projection, &viewport);
+        }
+        fb.resolve(image, config.tone_mapping, config.exposure);
+    }
+}
+
+pub struct Wireframe {
+    pub color: Rgba<u8>,
+    pub anti_aliased: bool,
+    pub hidden_line_removal: bool,
+    pub depth_bias: f32, // how far a line may lie behind the z buffer and still be drawn
+}
+
+impl Default for Wireframe {
+    fn default() -> Self {
+        Wireframe {
+            color: Rgba([255, 255, 255, 255]),
+            anti_aliased: true,
+            hidden_line_removal: true,
+            depth_bias: 2e-3,
+        }
+    }
+}
+
+// Draw the edges of every instance of the scene over the image. With hidden line removal
+// the scene is first rendered into a z buffer and line pixels behind it are skipped.
+pub fn render_wireframe(
+    scene: &Scene,
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    wireframe: &Wireframe,
+    config: &RenderConfig,
+) {
+    let viewport = screen_viewport(image.width(), image.height());
+    let view_proj = scene.camera.projection() * scene.camera.view();
+    let (width, height) = (image.width() as i32, image.height() as i32);
+
+    let mut fb = FrameBuffer::new(image.width(), image.height(), 1);
+    if wireframe.hidden_line_removal {
+        for instance in scene.instances.iter() {
+            let shader = DepthShader::new(view_proj * instance.transform.matrix(), instance.model);
+            draw_model(
+                instance.model,
+                &shader,
+                &viewport,
+                &mut fb,
+                config.cull_mode,
+                None,
+                config,
+            );
+        }
+    }
+
+    for instance in scene.instances.iter() {
+        let conv = view_proj * instance.transform.matrix();
+        for (a, b) in instance.model.get_edges() {
+            let pa = conv * instance.model.get_vertex(a).push(1.0);
+            let pb = conv * instance.model.get_vertex(b).push(1.0);
+            let Some((t0, t1)) = clip_line(&pa, &pb) else {
+                continue;
+            };
+            let (ca, cb) = (pa + (pb - pa) * t0, pa + (pb - pa) * t1);
+            let sa = viewport * (ca / ca.w);
+            let sb = viewport * (cb / cb.w);
+
+            let visible = |x: i32, y: i32, t: f32| {
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    return false;
+                }
+                let z = sa.z + (sb.z - sa.z) * t;
+                z + wireframe.depth_bias >= fb.depth[(x + y * width) as usize]
+            };
+            // screen coordinates put pixel centers at .5, the line walks put them at integers
+            if wireframe.anti_aliased {
+                wu(
+                    sa.x - 0.5,
+                    sa.y - 0.5,
+                    sb.x - 0.5,
+                    sb.y - 0.5,
+                    |x, y, t, c| {
+                        if visible(x, y, t) {
+                            blend_pixel(image, x as u32, y as u32, wireframe.color, c);
+                        }
+                    },
+                );
+            } else {
+                bresenham(
+                    (sa.x - 0.5).round() as i32,
+                    (sa.y - 0.5).round() as i32,
+                    (sb.x - 0.5).round() as i32,
+                    (sb.y - 0.5).round() as i32,
+                    |x, y, t| {
+                        if visible(x, y, t) {
+                            image.put_pixel(x as u32, y as u32, wireframe.color);
+                        }
+                    },
                 );
-                let mut color = Rgb([color[0], color[1], color[2]]);
-                shader.fragment(&mut color, bc_screen);
-                image.put_pixel(x as u32, y as u32, color);
             }
         }
     }
 }
 
-pub fn render_obj(model: &mut WModel, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
-    let mut projection = Matrix4::identity();
-    let viewport = geometry::get_viewport(
-        image.width() as f32 / 8.0,
-        image.height() as f32 / 8.0,
-        image.width() as f32 * 3.0 / 4.0,
-        image.height() as f32 * 3.0 / 4.0,
-    );
-    let mut lookat = geometry::get_lookat(
-        Vector3::new(LIGHT.x, LIGHT.y, LIGHT.z),
-        Vector3::new(0.0, 0.0, 0.0),
-        Vector3::new(0.0, 1.0, 0.0),
-    );
-    let cor_conv = viewport * projection * lookat;
-    let mut shadow_buf = vec![f32::MIN; (image.width() * image.height()) as usize];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // render shadow buffer
-    {
-        let mut shadow_img: ImageBuffer<Rgb<u8>, Vec<u8>> =
-            ImageBuffer::new(image.width(), image.height());
-        let mut shader = DepthShader::new(cor_conv, &model);
-        for i in 0..model.face_num {
-            let mut screen_coords = [Vector3::new(0.0, 0.0, 0.0); 3];
-            for j in 0..3 {
-                screen_coords[j] = shader.vertex(i, j)
+    // white opaque fragments, so that additive blending counts how often a pixel is drawn
+    struct Count;
+
+    impl Shader for Count {
+        type Varyings = ();
+
+        fn vertex(&self, _f_idx: usize, _v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
+            (Vector4::zeros(), ())
+        }
+
+        fn fragment(
+            &self,
+            _varyings: &Self::Varyings,
+            _ddx: &Self::Varyings,
+            _ddy: &Self::Varyings,
+            color: &mut ColorAlpha,
+        ) -> bool {
+            *color = ColorAlpha::repeat(1.0);
+            false
+        }
+    }
+
+    // discards every fragment
+    struct Discard;
+
+    impl Shader for Discard {
+        type Varyings = ();
+
+        fn vertex(&self, _f_idx: usize, _v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
+            (Vector4::zeros(), ())
+        }
+
+        fn fragment(
+            &self,
+            _varyings: &Self::Varyings,
+            _ddx: &Self::Varyings,
+            _ddy: &Self::Varyings,
+            color: &mut ColorAlpha,
+        ) -> bool {
+            *color = ColorAlpha::repeat(1.0);
+            true
+        }
+    }
+
+    fn screen_triangle(pts: [(f32, f32); 3]) -> ScreenTriangle<()> {
+        ScreenTriangle {
+            pts: pts.map(|(x, y)| Vector4::new(x, y, 0.0, 1.0)),
+            bars: [Vector3::x(), Vector3::y(), Vector3::z()],
+            varyings: [(); 3],
+        }
+    }
+
+    #[test]
+    fn shared_edge_pixels_are_drawn_once() {
+        let size = 32;
+        // axis aligned with corners on pixel centers, then rotated by arbitrary angles
+        let quads: Vec<[(f32, f32); 4]> = [0.0f32, 0.3, std::f32::consts::FRAC_PI_4, 1.1, 2.0, 4.0]
+            .iter()
+            .map(|&angle| {
+                let (sin, cos) = angle.sin_cos();
+                [(-10.0, -10.0), (10.0, -10.0), (10.0, 10.0), (-10.0, 10.0)]
+                    .map(|(x, y)| (16.5 + x * cos - y * sin, 16.5 + x * sin + y * cos))
+            })
+            .collect();
+
+        for q in quads {
+            let fb = FrameBuffer::new(size, size, 1);
+            let mut tile = Tile::new(0, 0, size as i32, size as i32, &fb);
+            for tri in [[q[0], q[1], q[2]], [q[0], q[2], q[3]]] {
+                triangle(
+                    &screen_triangle(tri),
+                    &mut tile,
+                    Msaa::Off.offsets(),
+                    &Count,
+                    Some(BlendMode::Additive),
+                );
             }
-            triangle(
-                &model,
-                i,
-                &screen_coords,
-                &mut shadow_img,
-                &mut shadow_buf,
-                &mut shader,
+
+            // signed distance of a pixel center to the quad, positive inside
+            let inside = |x: f32, y: f32| {
+                (0..4)
+                    .map(|i| {
+                        let (a, b) = (q[i], q[(i + 1) % 4]);
+                        let (ex, ey) = (b.0 - a.0, b.1 - a.1);
+                        (ex * (y - a.1) - ey * (x - a.0)) / (ex * ex + ey * ey).sqrt()
+                    })
+                    .fold(f32::MAX, f32::min)
+            };
+            for (i, color) in tile.color.iter().enumerate() {
+                let (x, y) = (
+                    (i as u32 % size) as f32 + 0.5,
+                    (i as u32 / size) as f32 + 0.5,
+                );
+                assert!(
+                    color.x <= 1.0,
+                    "({x}, {y}) of {q:?} drawn {} times",
+                    color.x
+                );
+                let d = inside(x, y);
+                if d.abs() > 1e-3 {
+                    assert_eq!(color.x, (d > 0.0) as u32 as f32, "({x}, {y}) of {q:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_threads_match_single_thread() {
+        use crate::{
+            framebuffer::Color,
+            img_io::{load_obj, make_plane},
+            scene::{Camera, Transform},
+        };
+
+        let head = WModel::new(load_obj("obj/african_head.obj"), None, None, None);
+        let floor = WModel::new(make_plane(), None, None, None);
+        let mut glass = WModel::new(make_plane(), None, None, None);
+        glass.material.diffuse = Color::new(0.2, 0.4, 1.0);
+        glass.material.opacity = 0.4;
+        glass.material.blend = Some(BlendMode::Alpha);
+
+        let (width, height) = (101, 77);
+        let mut scene = Scene::new(Camera::new(
+            Vector3::new(1.0, 1.0, 2.5),
+            Vector3::zeros(),
+            Vector3::y(),
+            std::f32::consts::FRAC_PI_4,
+            width as f32 / height as f32,
+        ));
+        scene.add_light(Light::new(Vector3::new(0.0, 3.0, 3.0)));
+        scene.add(&head, Transform::default());
+        scene.add(
+            &floor,
+            Transform::new(
+                Vector3::new(0.0, -0.8, 0.0),
+                Vector3::zeros(),
+                Vector3::repeat(1.5),
+            ),
+        );
+        scene.add(
+            &glass,
+            Transform::new(
+                Vector3::new(0.0, 0.0, 0.6),
+                Vector3::new(1.2, 0.0, 0.3),
+                Vector3::repeat(0.6),
+            ),
+        );
+
+        for msaa in [Msaa::Off, Msaa::X4, Msaa::X8] {
+            let render = |threads: usize| {
+                let config = RenderConfig {
+                    threads,
+                    tile_size: 7,
+                    msaa,
+                    shadows: ShadowSettings {
+                        resolution: 128,
+                        ..Default::default()
+                    },
+                    ssao: Some(Ssao::default()),
+                    outline: Some(Outline::default()),
+                    transparency_sort: TransparencySort::PerFace,
+                    ..RenderConfig::default()
+                };
+                let mut image = ImageBuffer::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+                render_scene(&scene, &mut image, &config);
+                image
+            };
+            assert!(
+                render(1) == render(3),
+                "{msaa:?} differs between 1 and 3 threads"
             );
         }
     }
 
-    let m = cor_conv;
+    #[test]
+    fn zero_scale_instance_renders() {
+        use crate::{
+            img_io::load_obj,
+            scene::{Camera, Transform},
+        };
 
-    // render frame buffer
-    {
-        projection = geometry::get_projection(FOVY, ASPECT, -1.0);
-        lookat = geometry::get_lookat(
-            Vector3::new(CAMERA.x, CAMERA.y, CAMERA.z),
-            Vector3::new(0.0, 0.0, 0.0),
-            Vector3::new(0.0, 1.0, 0.0),
+        let head = WModel::new(load_obj("obj/african_head.obj"), None, None, None);
+        let mut scene = Scene::new(Camera::new(
+            Vector3::new(1.0, 1.0, 2.5),
+            Vector3::zeros(),
+            Vector3::y(),
+            std::f32::consts::FRAC_PI_4,
+            1.0,
+        ));
+        scene.add_light(Light::new(Vector3::new(0.0, 3.0, 3.0)));
+        scene.add(
+            &head,
+            Transform::new(
+                Vector3::zeros(),
+                Vector3::zeros(),
+                Vector3::new(1.0, 0.0, 1.0),
+            ),
         );
-        let cor_conv = viewport * projection * lookat;
-
-        let mut z_buffer = vec![f32::MIN; (image.width() * image.height()) as usize];
-        let trans_nm = lookat.transpose().try_inverse().unwrap();
-        let trans_shadow = m * (cor_conv.try_inverse()).unwrap();
-        let mut shader =
-            GouphShader::new(cor_conv, &model, trans_nm, trans_shadow, lookat, shadow_buf);
-        for i in 0..model.face_num {
-            let mut screen_coords = [Vector3::new(0.0, 0.0, 0.0); 3];
-            for j in 0..3 {
-                screen_coords[j] = shader.vertex(i, j)
-            }
-            triangle(&model, i, &screen_coords, image, &mut z_buffer, &mut shader);
+        let config = RenderConfig {
+            threads: 1,
+            shadows: ShadowSettings {
+                resolution: 64,
+                ..Default::default()
+            },
+            ..RenderConfig::default()
+        };
+        let mut image = ImageBuffer::from_pixel(32, 32, Rgba([0, 0, 0, 255]));
+        render_scene(&scene, &mut image, &config);
+    }
+
+    #[test]
+    fn discarded_fragments_leave_the_tile_untouched() {
+        let fb = FrameBuffer::new(16, 16, 4);
+        let mut tile = Tile::new(0, 0, 16, 16, &fb);
+        let background = ColorAlpha::new(0.1, 0.2, 0.3, 1.0);
+        tile.color.fill(background);
+        let tri = screen_triangle([(1.0, 1.0), (15.0, 2.0), (8.0, 15.0)]);
+        for blend in [None, Some(BlendMode::Alpha), Some(BlendMode::Additive)] {
+            triangle(&tri, &mut tile, Msaa::X4.offsets(), &Discard, blend);
+            assert!(tile.color.iter().all(|&c| c == background), "{blend:?}");
+            assert!(tile.depth.iter().all(|&z| z == f32::MIN), "{blend:?}");
         }
     }
+
+    #[test]
+    fn cut_out_model_is_invisible_and_casts_no_shadow() {
+        use crate::{
+            img_io::{load_obj, make_plane},
+            scene::{Camera, Transform},
+        };
+
+        // the head keeps the scene bounds, and so the shadow map fit, the same with or without
+        // the cutout
+        let head = WModel::new(load_obj("obj/african_head.obj"), None, None, None);
+        let floor = WModel::new(make_plane(), None, None, None);
+        let mut cutout = WModel::new(make_plane(), None, None, None);
+        cutout.material.opacity = 0.2;
+        cutout.material.alpha_cutoff = Some(0.5);
+
+        let render = |with_cutout: bool| {
+            let mut scene = Scene::new(Camera::new(
+                Vector3::new(0.0, 2.0, 2.5),
+                Vector3::zeros(),
+                Vector3::y(),
+                std::f32::consts::FRAC_PI_4,
+                1.0,
+            ));
+            scene.add_light(Light::new(Vector3::new(0.2, 1.0, 0.3)));
+            scene.add(&head, Transform::default());
+            scene.add(
+                &floor,
+                Transform::new(
+                    Vector3::new(0.0, -0.8, 0.0),
+                    Vector3::zeros(),
+                    Vector3::repeat(1.5),
+                ),
+            );
+            if with_cutout {
+                scene.add(
+                    &cutout,
+                    Transform::new(
+                        Vector3::new(0.0, 0.5, 0.0),
+                        Vector3::zeros(),
+                        Vector3::repeat(0.7),
+                    ),
+                );
+            }
+            let config = RenderConfig {
+                threads: 1,
+                shadows: ShadowSettings {
+                    resolution: 128,
+                    ..Default::default()
+                },
+                ..RenderConfig::default()
+            };
+            let mut image = ImageBuffer::from_pixel(48, 48, Rgba([0, 0, 0, 255]));
+            render_scene(&scene, &mut image, &config);
+            image
+        };
+        assert!(render(true) == render(false));
+    }
 }
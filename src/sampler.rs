@@ -0,0 +1,298 @@
+use nalgebra::{SVector, Vector2, Vector4};
+
+use crate::texture::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic, // Catmull-Rom spline through the 4x4 nearest texels
+}
+
+// how the mip level is chosen from the uv footprint of a pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    None,    // always sample level 0
+    Nearest, // closest level
+    Linear,  // blend of the two closest levels, trilinear with the bilinear filter
+}
+
+// how texel coordinates outside the texture are mapped back into it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    fn apply(&self, i: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as u32
+    }
+}
+
+// Texture lookup with uv coordinates, (0, 0) is the corner of the first texel and (1, 1)
+// the far corner of the last one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub mip_filter: MipFilter,
+    pub max_anisotropy: u32, // taps along the longer axis of the footprint, 1 for isotropic
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: Wrap) -> Self {
+        Sampler {
+            filter,
+            wrap,
+            mip_filter: MipFilter::Linear,
+            max_anisotropy: 1,
+        }
+    }
+
+    // Sample a mip chain of `levels` levels, `size` gives the dimensions of a level and
+    // `texel` fetches from it. `ddx` and `ddy` are the uv derivatives along the screen axes,
+    // they select the level and the direction of anisotropic taps.
+    pub fn sample_mip<const D: usize, S, F>(
+        &self,
+        levels: usize,
+        size: S,
+        uv: Vector2<f32>,
+        ddx: Vector2<f32>,
+        ddy: Vector2<f32>,
+        texel: F,
+    ) -> SVector<f32, D>
+    where
+        S: Fn(usize) -> (u32, u32),
+        F: Fn(usize, u32, u32) -> SVector<f32, D>,
+    {
+        let (width, height) = size(0);
+        let texels = Vector2::new(width as f32, height as f32);
+        let (len_x, len_y) = (
+            ddx.component_mul(&texels).norm(),
+            ddy.component_mul(&texels).norm(),
+        );
+        let (axis, major, minor) = if len_x >= len_y {
+            (ddx, len_x, len_y)
+        } else {
+            (ddy, len_y, len_x)
+        };
+        let taps = if self.max_anisotropy > 1 && minor > 0.0 {
+            ((major / minor).ceil() as u32).clamp(1, self.max_anisotropy)
+        } else {
+            1
+        };
+        let lod = match self.mip_filter {
+            MipFilter::None => 0.0,
+            _ => (major / taps as f32)
+                .max(1e-8)
+                .log2()
+                .clamp(0.0, (levels - 1) as f32),
+        };
+
+        let level = |l: usize, uv: Vector2<f32>| {
+            let (w, h) = size(l);
+            self.sample(w, h, uv, |x, y| texel(l, x, y))
+        };
+        let at_lod = |uv: Vector2<f32>| match self.mip_filter {
+            MipFilter::None => level(0, uv),
+            MipFilter::Nearest => level(lod.round() as usize, uv),
+            MipFilter::Linear => {
+                let (l0, t) = (lod.floor() as usize, lod.fract());
+                if t == 0.0 {
+                    level(l0, uv)
+                } else {
+                    level(l0, uv).lerp(&level(l0 + 1, uv), t)
+                }
+            }
+        };
+
+        if taps == 1 {
+            return at_lod(uv);
+        }
+        let mut sum = SVector::zeros();
+        for i in 0..taps {
+            let offset = (i as f32 + 0.5) / taps as f32 - 0.5;
+            sum += at_lod(uv + axis * offset);
+        }
+        sum / taps as f32
+    }
+
+    // RGBA of a texture in [0, 1] with the color in linear space, the bicubic filter may
+    // overshoot slightly
+    pub fn sample_texture(
+        &self,
+        tex: &Texture,
+        uv: Vector2<f32>,
+        ddx: Vector2<f32>,
+        ddy: Vector2<f32>,
+    ) -> Vector4<f32> {
+        self.sample_mip(
+            tex.levels.len(),
+            |l| tex.levels[l].dimensions(),
+            uv,
+            ddx,
+            ddy,
+            |l, x, y| tex.texel(l, x, y),
+        )
+    }
+
+    // Sample a single level, `texel` fetches the value at integer coordinates inside the
+    // `width` x `height` texture.
+    pub fn sample<const D: usize, F>(
+        &self,
+        width: u32,
+        height: u32,
+        uv: Vector2<f32>,
+        texel: F,
+    ) -> SVector<f32, D>
+    where
+        F: Fn(u32, u32) -> SVector<f32, D>,
+    {
+        let fetch = |x: i64, y: i64| texel(self.wrap.apply(x, width), self.wrap.apply(y, height));
+        let x = uv.x * width as f32;
+        let y = uv.y * height as f32;
+        match self.filter {
+            Filter::Nearest => fetch(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = fetch(x0, y0).lerp(&fetch(x0 + 1, y0), tx);
+                let bottom = fetch(x0, y0 + 1).lerp(&fetch(x0 + 1, y0 + 1), tx);
+                top.lerp(&bottom, ty)
+            }
+            Filter::Bicubic => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (wx, wy) = (catmull_rom(x - x0), catmull_rom(y - y0));
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let mut sum = SVector::zeros();
+                for (j, wy) in wy.iter().enumerate() {
+                    for (i, wx) in wx.iter().enumerate() {
+                        sum += fetch(x0 + i as i64 - 1, y0 + j as i64 - 1) * (wx * wy);
+                    }
+                }
+                sum
+            }
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler::new(Filter::Bilinear, Wrap::Repeat)
+    }
+}
+
+// weights of the texels at -1, 0, 1 and 2 for a position `t` in [0, 1) past texel 0
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector1;
+
+    #[test]
+    fn wrap_out_of_range_indices() {
+        let cases = [
+            (Wrap::Repeat, [(-1, 3), (-5, 3), (4, 0), (9, 1)]),
+            (Wrap::Clamp, [(-1, 0), (-100, 0), (4, 3), (9, 3)]),
+            (Wrap::Mirror, [(-1, 0), (-5, 3), (4, 3), (9, 1)]),
+        ];
+        for (wrap, indices) in cases {
+            for (i, expected) in indices {
+                assert_eq!(wrap.apply(i, 4), expected, "{wrap:?} {i}");
+            }
+            for i in 0..4 {
+                assert_eq!(wrap.apply(i, 4), i as u32, "{wrap:?} {i}");
+            }
+        }
+        assert_eq!(Wrap::Mirror.apply(-2, 4), 1);
+        assert_eq!(Wrap::Mirror.apply(8, 4), 0);
+    }
+
+    #[test]
+    fn filters_wrap_coordinates_outside_the_texture() {
+        // texel (x, y) of a 4x4 texture holds x + 4y, so sampling at (u, u) gives 5 times the
+        // value along one axis
+        let expected = [
+            (Filter::Nearest, Wrap::Repeat, [0.0, 3.0, 3.0]),
+            (Filter::Nearest, Wrap::Clamp, [3.0, 0.0, 3.0]),
+            (Filter::Nearest, Wrap::Mirror, [3.0, 0.0, 0.0]),
+            (Filter::Bilinear, Wrap::Repeat, [1.5, 2.5, 2.7]),
+            (Filter::Bilinear, Wrap::Clamp, [3.0, 0.0, 3.0]),
+            (Filter::Bilinear, Wrap::Mirror, [3.0, 0.5, 0.0]),
+            (Filter::Bicubic, Wrap::Repeat, [1.5, 2.75, 2.844]),
+            (Filter::Bicubic, Wrap::Clamp, [3.0625, 0.0, 3.0]),
+            (Filter::Bicubic, Wrap::Mirror, [3.125, 0.4375, -0.045]),
+        ];
+        for (filter, wrap, values) in expected {
+            let sampler = Sampler::new(filter, wrap);
+            for (u, value) in [1.0, -0.25, 7.9].into_iter().zip(values) {
+                let sample = sampler.sample(4, 4, Vector2::repeat(u), |x, y| {
+                    assert!(x < 4 && y < 4, "{filter:?} {wrap:?} fetched ({x}, {y})");
+                    Vector1::new((x + 4 * y) as f32)
+                });
+                assert!(
+                    (sample.x - 5.0 * value).abs() < 1e-3,
+                    "{filter:?} {wrap:?} at {u}: {}",
+                    sample.x / 5.0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mipped_texture_samples_outside_the_texture() {
+        use crate::texture::ColorSpace;
+        use image::{ImageBuffer, Rgba};
+
+        let image =
+            ImageBuffer::from_fn(5, 3, |x, y| Rgba([(x * 50) as u8, (y * 100) as u8, 0, 255]));
+        let tex = Texture::new(image, ColorSpace::Linear);
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::Bicubic] {
+            for wrap in [Wrap::Repeat, Wrap::Clamp, Wrap::Mirror] {
+                let sampler = Sampler::new(filter, wrap);
+                for u in [1.0, -0.25, 7.9] {
+                    // from level 0 up to past the last level
+                    for d in [0.0, 0.3, 1.0, 4.0] {
+                        let c = sampler.sample_texture(
+                            &tex,
+                            Vector2::repeat(u),
+                            Vector2::new(d, 0.0),
+                            Vector2::new(0.0, d),
+                        );
+                        assert!(
+                            (c.w - 1.0).abs() < 1e-5,
+                            "{filter:?} {wrap:?} at {u} with {d}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
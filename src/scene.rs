@@ -0,0 +1,220 @@
+use nalgebra::{Matrix4, Rotation3, Vector3};
+
+use crate::{framebuffer::Color, geometry, img_io::WModel};
+
+pub struct Camera {
+    pub eye: Vector3<f32>,
+    pub target: Vector3<f32>,
+    pub up: Vector3<f32>,
+    pub fovy: f32,
+    pub aspect: f32,
+    pub near: f32, // distance to the near clipping plane
+}
+
+impl Camera {
+    pub fn new(
+        eye: Vector3<f32>,
+        target: Vector3<f32>,
+        up: Vector3<f32>,
+        fovy: f32,
+        aspect: f32,
+    ) -> Self {
+        Camera {
+            eye,
+            target,
+            up,
+            fovy,
+            aspect,
+            near: 1.0,
+        }
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        geometry::get_lookat(self.eye, self.target, self.up)
+    }
+
+    pub fn projection(&self) -> Matrix4<f32> {
+        geometry::get_projection(self.fovy, self.aspect, -self.near)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    // half angles of the cone in radians, full intensity inside `inner`, none outside `outer`
+    Spot { inner: f32, outer: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub dir: Vector3<f32>, // direction from the scene towards the light, along the cone axis for spots
+    pub position: Vector3<f32>, // unused by directional lights
+    pub color: Color,
+    pub intensity: f32,
+    pub range: f32, // distance at which point and spot lights fade out, 0 for no limit
+}
+
+impl Light {
+    // white directional light
+    pub fn new(dir: Vector3<f32>) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            dir: dir.normalize(),
+            position: Vector3::zeros(),
+            color: Color::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            range: 0.0,
+        }
+    }
+
+    // white point light, its shadow looks at the center of the scene bounds
+    pub fn point(position: Vector3<f32>, range: f32) -> Self {
+        Light {
+            kind: LightKind::Point,
+            position,
+            range,
+            ..Light::new(position)
+        }
+    }
+
+    // white spot light at `position` pointing at `target`
+    pub fn spot(
+        position: Vector3<f32>,
+        target: Vector3<f32>,
+        range: f32,
+        inner: f32,
+        outer: f32,
+    ) -> Self {
+        Light {
+            kind: LightKind::Spot { inner, outer },
+            position,
+            range,
+            ..Light::new(position - target)
+        }
+    }
+
+    // the same light with its position and direction transformed by `m`
+    pub fn transformed(&self, m: &Matrix4<f32>) -> Light {
+        let p = m * self.position.push(1.0);
+        Light {
+            dir: (m * self.dir.push(0.0)).xyz().normalize(),
+            position: p.xyz() / p.w,
+            ..*self
+        }
+    }
+
+    // Direction towards the light and the radiance arriving at `p`. Point and spot lights
+    // fall off with the inverse square of the distance, smoothly windowed to 0 at `range`.
+    pub fn incident(&self, p: &Vector3<f32>) -> (Vector3<f32>, Color) {
+        if self.kind == LightKind::Directional {
+            return (self.dir, self.color * self.intensity);
+        }
+        let d = self.position - p;
+        let dist2 = d.norm_squared().max(1e-4);
+        let l = d / dist2.sqrt();
+        let mut attenuation = 1.0 / dist2;
+        if self.range > 0.0 {
+            let window = (1.0 - (dist2 / (self.range * self.range)).powi(2)).clamp(0.0, 1.0);
+            attenuation *= window * window;
+        }
+        if let LightKind::Spot { inner, outer } = self.kind {
+            let cos_angle = l.dot(&self.dir);
+            let (cos_inner, cos_outer) = (inner.cos(), outer.cos());
+            let t = ((cos_angle - cos_outer) / (cos_inner - cos_outer).max(1e-4)).clamp(0.0, 1.0);
+            attenuation *= t * t * (3.0 - 2.0 * t);
+        }
+        (l, self.color * (self.intensity * attenuation))
+    }
+}
+
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Vector3<f32>, // euler angles around x, y and z in radians
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn new(translation: Vector3<f32>, rotation: Vector3<f32>, scale: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // model matrix, scales first, then rotates and translates
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let rotation =
+            Rotation3::from_euler_angles(self.rotation.x, self.rotation.y, self.rotation.z);
+        Matrix4::new_translation(&self.translation)
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(
+            Vector3::zeros(),
+            Vector3::zeros(),
+            Vector3::new(1.0, 1.0, 1.0),
+        )
+    }
+}
+
+// a model placed in the scene, the same model may be placed several times
+pub struct Instance<'a> {
+    pub model: &'a WModel,
+    pub transform: Transform,
+}
+
+// The first light of the list casts the shadows.
+pub struct Scene<'a> {
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub instances: Vec<Instance<'a>>,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(camera: Camera) -> Self {
+        Scene {
+            camera,
+            lights: Vec::new(),
+            instances: Vec::new(),
+        }
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn add(&mut self, model: &'a WModel, transform: Transform) {
+        self.instances.push(Instance { model, transform });
+    }
+
+    // world space bounding box of all instances as (min, max)
+    pub fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        if self.instances.is_empty() {
+            return (Vector3::zeros(), Vector3::zeros());
+        }
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+        for instance in self.instances.iter() {
+            let (lo, hi) = instance.model.bounds();
+            let m = instance.transform.matrix();
+            for i in 0..8 {
+                let corner = Vector3::new(
+                    if i & 1 == 0 { lo.x } else { hi.x },
+                    if i & 2 == 0 { lo.y } else { hi.y },
+                    if i & 4 == 0 { lo.z } else { hi.z },
+                );
+                let p = (m * corner.push(1.0)).xyz();
+                min = min.inf(&p);
+                max = max.sup(&p);
+            }
+        }
+        (min, max)
+    }
+}
@@ -1,109 +1,440 @@
-use image::Rgb;
-use nalgebra::{Matrix2x3, Matrix3, Matrix3x2, Matrix4, Vector2, Vector3, Vector4};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use std::f32::consts::PI;
 
 use crate::{
-    consts::{LIGHT, LIGHT_DIR, WIDTH},
-    img_io::WModel,
+    framebuffer::{Color, ColorAlpha},
+    geometry,
+    img_io::{NormalMapSpace, WModel},
+    material::Material,
+    sampler::{Filter, Sampler, Wrap},
+    scene::Light,
+    shadow::{ShadowMap, ShadowSettings},
+    texture::Texture,
 };
 
-pub trait Shader {
-    fn vertex(&mut self, f_idx: usize, v_idx: usize) -> Vector3<f32>;
-    fn fragment(&mut self, color: &mut Rgb<u8>, bar: Vector3<f32>) -> bool;
+// values output by the vertex shader and interpolated across the face for the fragment shader
+pub trait Varying: Copy + Send + Sync {
+    // weighted sum of the values at the three corners of a face
+    fn interpolate(v: &[Self; 3], bar: Vector3<f32>) -> Self;
 }
 
-pub struct GouphShader<'a> {
-    varyng_uv: Matrix2x3<f32>,
-    varyng_tri: Matrix3<f32>,
-    coord_conv: Matrix4<f32>,
+impl Varying for () {
+    fn interpolate(_v: &[Self; 3], _bar: Vector3<f32>) -> Self {}
+}
+
+impl Varying for f32 {
+    fn interpolate(v: &[Self; 3], bar: Vector3<f32>) -> Self {
+        v[0] * bar.x + v[1] * bar.y + v[2] * bar.z
+    }
+}
+
+macro_rules! impl_varying_vector {
+    ($($t:ty),*) => {$(
+        impl Varying for $t {
+            fn interpolate(v: &[Self; 3], bar: Vector3<f32>) -> Self {
+                v[0] * bar.x + v[1] * bar.y + v[2] * bar.z
+            }
+        }
+    )*};
+}
+
+impl_varying_vector!(Vector2<f32>, Vector3<f32>, Vector4<f32>);
+
+macro_rules! impl_varying_tuple {
+    ($($name:ident $idx:tt),*) => {
+        impl<$($name: Varying),*> Varying for ($($name,)*) {
+            fn interpolate(v: &[Self; 3], bar: Vector3<f32>) -> Self {
+                ($($name::interpolate(&[v[0].$idx, v[1].$idx, v[2].$idx], bar),)*)
+            }
+        }
+    };
+}
+
+impl_varying_tuple!(A 0, B 1);
+impl_varying_tuple!(A 0, B 1, C 2);
+impl_varying_tuple!(A 0, B 1, C 2, D 3);
+impl_varying_tuple!(A 0, B 1, C 2, D 3, E 4);
+
+// Shaders are shared between the rasterizer threads, so they must not keep per-face state,
+// everything a fragment needs is passed through the varyings.
+pub trait Shader: Sync {
+    type Varyings: Varying;
+
+    // clip space position of a corner of a face and its varyings
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, Self::Varyings);
+
+    // `varyings` are interpolated perspective-correctly at the pixel,
+    // `ddx` and `ddy` are their differences to the neighboring pixels of the same 2x2 quad,
+    // `color` receives the high dynamic range result with its alpha,
+    // returning true discards the fragment without writing color or depth
+    fn fragment(
+        &self,
+        varyings: &Self::Varyings,
+        ddx: &Self::Varyings,
+        ddy: &Self::Varyings,
+        color: &mut ColorAlpha,
+    ) -> bool;
+}
+
+// Model space normal at a fragment. Object space normal maps are read directly, tangent
+// space ones are rotated into the frame of the interpolated vertex normal and tangent.
+// Without a normal map the interpolated vertex normal is used.
+fn shading_normal(
+    model: &WModel,
+    uv: (Vector2<f32>, Vector2<f32>, Vector2<f32>), // uv and its screen space derivatives
+    normal: Vector3<f32>,
+    tangent: Vector4<f32>,
+) -> Vector3<f32> {
+    let Some(mapped) = model.get_normal_tex(uv.0, uv.1, uv.2) else {
+        return normal.normalize();
+    };
+    match model.normal_space {
+        NormalMapSpace::Object => mapped,
+        NormalMapSpace::Tangent => {
+            let n = normal.normalize();
+            let t = tangent.xyz();
+            let t = (t - n * n.dot(&t)).normalize();
+            let b = tangent.w * n.cross(&t);
+            (t * mapped.x + b * mapped.y + n * mapped.z).normalize()
+        }
+    }
+}
+
+// texture coordinates, model space position, view space position,
+// model space normal and tangent
+pub type SurfaceVaryings = (
+    Vector2<f32>,
+    Vector3<f32>,
+    Vector3<f32>,
+    Vector3<f32>,
+    Vector4<f32>,
+);
+
+// State shared by the lighting models of the main pass: the transforms, the lights and the
+// shadow map. It also runs their common vertex stage.
+pub struct SurfaceInputs<'a> {
+    projection: Matrix4<f32>,
+    model_view: Matrix4<f32>,
     model: &'a WModel,
     trans_nm: Matrix4<f32>,
     trans_shadow: Matrix4<f32>,
-    trans_light: Matrix4<f32>,
-    shadow_buf: Vec<f32>,
-}
-
-impl<'a> GouphShader<'a> {
-    const K_D: f32 = 1.0;
-    const K_S: f32 = 3.5;
+    lights: &'a [Light],
+    shadow_map: Option<&'a ShadowMap>,
+    shadow_settings: ShadowSettings,
 }
 
-impl<'a> GouphShader<'a> {
+impl<'a> SurfaceInputs<'a> {
     pub fn new(
-        coord_conv: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        model_view: Matrix4<f32>,
         model: &'a WModel,
-        trans_nm: Matrix4<f32>,     // normal convert matrix
-        trans_shadow: Matrix4<f32>, // shadow convert matrix
-        trans_light: Matrix4<f32>,  // light convert matrix
-        shadow_buf: Vec<f32>,
+        trans_shadow: Matrix4<f32>, // model to shadow map convert matrix
+        lights: &'a [Light],        // lights in view space, the first one casts shadows
+        shadow_map: Option<&'a ShadowMap>,
+        shadow_settings: ShadowSettings,
     ) -> Self {
-        GouphShader {
-            varyng_uv: Matrix2x3::zeros(),
-            varyng_tri: Matrix3::zeros(),
-            coord_conv,
+        SurfaceInputs {
+            projection,
+            model_view,
             model,
-            trans_nm,
+            trans_nm: geometry::get_normal_matrix(&model_view),
             trans_shadow,
-            trans_light,
-            shadow_buf,
+            lights,
+            shadow_map,
+            shadow_settings,
         }
     }
 
-    fn convert_normal(&self, nm: &Vector3<f32>) -> Vector3<f32> {
-        let nm_ = self.trans_nm * Vector4::new(nm.x, nm.y, nm.z, 1.0);
-        Vector3::new(nm_.x, nm_.y, nm_.z)
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, SurfaceVaryings) {
+        let uv = self.model.get_uv(f_idx, v_idx);
+        let v = self.model.get_vertex(self.model.get_face(f_idx)[v_idx]);
+        let p = self.model_view * Vector4::new(v.x, v.y, v.z, 1.0);
+        let normal = self.model.get_face_normal(f_idx)[v_idx];
+        let tangent = self.model.get_tangent(f_idx, v_idx);
+        (self.projection * p, (uv, v, p.xyz() / p.w, normal, tangent))
+    }
+
+    // view space shading normal at a fragment
+    fn normal(
+        &self,
+        varyings: &SurfaceVaryings,
+        ddx: &SurfaceVaryings,
+        ddy: &SurfaceVaryings,
+    ) -> Vector3<f32> {
+        let (uv, _, _, normal, tangent) = *varyings;
+        let nm = shading_normal(self.model, (uv, ddx.0, ddy.0), normal, tangent);
+        let nm_ = self.trans_nm * Vector4::new(nm.x, nm.y, nm.z, 0.0);
+        Vector3::new(nm_.x, nm_.y, nm_.z).normalize()
+    }
+
+    // fraction of the `i`th light reaching the model space point `p`, only the first light
+    // casts shadows
+    fn shadow(&self, i: usize, p: Vector3<f32>, n_dot_l: f32) -> f32 {
+        match (i, self.shadow_map) {
+            (0, Some(shadow_map)) => {
+                let shadow_p = self.trans_shadow * p.push(1.0);
+                shadow_map.intensity(&self.shadow_settings, shadow_p, n_dot_l)
+            }
+            _ => 1.0,
+        }
     }
 }
 
-impl Shader for GouphShader<'_> {
-    fn vertex(&mut self, f_idx: usize, v_idx: usize) -> Vector3<f32> {
-        let uv = self.model.get_uv(f_idx, v_idx);
-        self.varyng_uv[(0, v_idx)] = uv.x;
-        self.varyng_uv[(1, v_idx)] = uv.y;
-        let v = self.model.get_vertex(self.model.get_face(f_idx)[v_idx]);
-        let v = self.coord_conv * Vector4::new(v.x, v.y, v.z, 1.0);
-        let v = Vector3::new(v.x / v.w, v.y / v.w, v.z / v.w);
-        self.varyng_tri[(0, v_idx)] = v.x;
-        self.varyng_tri[(1, v_idx)] = v.y;
-        self.varyng_tri[(2, v_idx)] = v.z;
-        v
-    }
-
-    fn fragment(&mut self, color: &mut Rgb<u8>, bar: Vector3<f32>) -> bool {
-        let uv = self.varyng_uv * bar;
-        let p = self.varyng_tri * bar;
-
-        let normal = self.model.get_normal_tex(uv);
-        let normal = self.convert_normal(&normal);
-
-        let shadow_p = self.trans_shadow * Vector4::new(p.x, p.y, p.z, 1.0);
-        let shadow_p = Vector3::new(
-            shadow_p.x / shadow_p.w,
-            shadow_p.y / shadow_p.w,
-            shadow_p.z / shadow_p.w,
-        );
-        let shadow_idx = shadow_p.x as usize + shadow_p.y as usize * WIDTH as usize;
-        let shadow_intensity =
-            0.3 + 0.7 * (self.shadow_buf[shadow_idx] < shadow_p.z + 0.01) as u8 as f32;
-
-        let l = self.trans_light * Vector4::new(LIGHT_DIR.x, LIGHT_DIR.y, LIGHT_DIR.z, 0.0);
-        let l = Vector3::new(l.x, l.y, l.z).normalize();
-        let r = (2.0 * normal.dot(&l) * normal - l).normalize();
-
-        let intensity = normal.dot(&l);
-
-        let spec_color = self.model.specular_tex.get_pixel(
-            (uv.x * self.model.specular_tex.width() as f32) as u32,
-            (uv.y * self.model.specular_tex.height() as f32) as u32,
-        );
-        let specular_i = f32::max(r.z, 0.0).powf(5.0 + spec_color[0] as f32);
-
-        for i in 0..3 {
-            color[i] = (10.0
-                + (color[i] as f32)
-                    * shadow_intensity
-                    * (GouphShader::K_D * intensity + (GouphShader::K_S * specular_i)))
-                .min(255.0) as u8;
+// true when the material cuts out a fragment of this alpha
+fn cut_out(material: &Material, alpha: f32) -> bool {
+    material.alpha_cutoff.is_some_and(|cutoff| alpha < cutoff)
+}
+
+pub struct BlinnPhongShader<'a> {
+    inputs: SurfaceInputs<'a>,
+}
+
+impl<'a> BlinnPhongShader<'a> {
+    pub fn new(inputs: SurfaceInputs<'a>) -> Self {
+        BlinnPhongShader { inputs }
+    }
+}
+
+impl Shader for BlinnPhongShader<'_> {
+    type Varyings = SurfaceVaryings;
+
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
+        self.inputs.vertex(f_idx, v_idx)
+    }
+
+    fn fragment(
+        &self,
+        varyings: &Self::Varyings,
+        ddx: &Self::Varyings,
+        ddy: &Self::Varyings,
+        color: &mut ColorAlpha,
+    ) -> bool {
+        let (uv, p, p_view, _, _) = *varyings;
+        let (uv_dx, uv_dy) = (ddx.0, ddy.0);
+        let model = self.inputs.model;
+        let material = &model.material;
+
+        let diffuse = model.get_diffuse(uv, uv_dx, uv_dy);
+        let alpha = diffuse.w * material.opacity;
+        if cut_out(material, alpha) {
+            return true;
+        }
+        let albedo = diffuse.xyz();
+        let normal = self.inputs.normal(varyings, ddx, ddy);
+        let specular_map = model.get_specular(uv, uv_dx, uv_dy);
+        let diffuse_color = material.diffuse.component_mul(&albedo) * material.diffuse_strength;
+        let specular_color = material.specular * (material.specular_strength * specular_map);
+
+        // the camera sits at the origin of view space
+        let v = (-p_view).normalize();
+        let mut rgb = material.ambient.component_mul(&albedo) * material.ambient_strength;
+        for (i, light) in self.inputs.lights.iter().enumerate() {
+            let (l, radiance) = light.incident(&p_view);
+            let diffuse_i = normal.dot(&l);
+            if diffuse_i <= 0.0 {
+                continue;
+            }
+            let h = (l + v).normalize();
+            let specular_i = normal.dot(&h).max(0.0).powf(material.shininess);
+            let lit = (diffuse_color * diffuse_i + specular_color * specular_i)
+                * self.inputs.shadow(i, p, diffuse_i);
+            rgb += lit.component_mul(&radiance);
+        }
+        *color = rgb.push(alpha);
+        false
+    }
+}
+
+// Cel shading: the diffuse term of every light is quantized into `Material::bands` flat
+// levels, or looked up in the model's ramp texture, and highlights have a hard edge.
+pub struct ToonShader<'a> {
+    inputs: SurfaceInputs<'a>,
+}
+
+impl<'a> ToonShader<'a> {
+    pub fn new(inputs: SurfaceInputs<'a>) -> Self {
+        ToonShader { inputs }
+    }
+
+    // lighting level for a diffuse intensity in [0, 1]
+    fn ramp(&self, intensity: f32) -> Color {
+        match &self.inputs.model.ramp_tex {
+            Some(ramp) => Sampler::new(Filter::Bilinear, Wrap::Clamp)
+                .sample_texture(
+                    ramp,
+                    Vector2::new(intensity, 0.5),
+                    Vector2::zeros(),
+                    Vector2::zeros(),
+                )
+                .xyz(),
+            None => {
+                let bands = self.inputs.model.material.bands.max(2) as f32;
+                Color::repeat(((intensity * bands).floor() / (bands - 1.0)).min(1.0))
+            }
+        }
+    }
+}
+
+impl Shader for ToonShader<'_> {
+    type Varyings = SurfaceVaryings;
+
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
+        self.inputs.vertex(f_idx, v_idx)
+    }
+
+    fn fragment(
+        &self,
+        varyings: &Self::Varyings,
+        ddx: &Self::Varyings,
+        ddy: &Self::Varyings,
+        color: &mut ColorAlpha,
+    ) -> bool {
+        let (uv, p, p_view, _, _) = *varyings;
+        let (uv_dx, uv_dy) = (ddx.0, ddy.0);
+        let model = self.inputs.model;
+        let material = &model.material;
+
+        let diffuse = model.get_diffuse(uv, uv_dx, uv_dy);
+        let alpha = diffuse.w * material.opacity;
+        if cut_out(material, alpha) {
+            return true;
+        }
+        let albedo = diffuse.xyz();
+        let normal = self.inputs.normal(varyings, ddx, ddy);
+        let specular_map = model.get_specular(uv, uv_dx, uv_dy);
+        let diffuse_color = material.diffuse.component_mul(&albedo) * material.diffuse_strength;
+        let specular_color = material.specular * (material.specular_strength * specular_map);
+
+        let v = (-p_view).normalize();
+        let mut rgb = material.ambient.component_mul(&albedo) * material.ambient_strength;
+        for (i, light) in self.inputs.lights.iter().enumerate() {
+            let (l, radiance) = light.incident(&p_view);
+            let mut diffuse_i = normal.dot(&l).max(0.0);
+            diffuse_i *= self.inputs.shadow(i, p, diffuse_i);
+            let mut lit = diffuse_color.component_mul(&self.ramp(diffuse_i));
+            let h = (l + v).normalize();
+            if diffuse_i > 0.0 && normal.dot(&h).max(0.0).powf(material.shininess) > 0.5 {
+                lit += specular_color;
+            }
+            rgb += lit.component_mul(&radiance);
+        }
+        *color = rgb.push(alpha);
+        false
+    }
+}
+
+// Metallic-roughness shading with a Cook-Torrance specular BRDF: GGX normal distribution,
+// Smith-Schlick geometry term and Schlick fresnel. The diffuse lobe is Lambertian, scaled
+// by the energy not reflected specularly and removed entirely for metals.
+pub struct PbrShader<'a> {
+    inputs: SurfaceInputs<'a>,
+}
+
+impl<'a> PbrShader<'a> {
+    // reflectance at normal incidence of dielectrics
+    const F0_DIELECTRIC: f32 = 0.04;
+
+    pub fn new(inputs: SurfaceInputs<'a>) -> Self {
+        PbrShader { inputs }
+    }
+
+    // reflected fraction of the light arriving from `l` towards `v`, diffuse plus specular
+    fn brdf(
+        n: &Vector3<f32>,
+        v: &Vector3<f32>,
+        l: &Vector3<f32>,
+        base_color: &Color,
+        metallic: f32,
+        roughness: f32,
+    ) -> Color {
+        let h = (l + v).normalize();
+        let n_dot_l = n.dot(l).max(0.0);
+        let n_dot_v = n.dot(v).max(1e-4);
+        let n_dot_h = n.dot(&h).max(0.0);
+        let h_dot_v = h.dot(v).max(0.0);
+
+        let f0 = Color::repeat(Self::F0_DIELECTRIC).lerp(base_color, metallic);
+        let fresnel = f0 + (Color::repeat(1.0) - f0) * (1.0 - h_dot_v).powi(5);
+
+        let alpha = roughness * roughness;
+        let alpha2 = alpha * alpha;
+        let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+        let distribution = alpha2 / (PI * d * d);
+
+        let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+        let geometry =
+            (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+
+        let specular = fresnel * (distribution * geometry / (4.0 * n_dot_v * n_dot_l).max(1e-4));
+        let k_d = (Color::repeat(1.0) - fresnel) * (1.0 - metallic);
+        k_d.component_mul(base_color) / PI + specular
+    }
+
+    // red channel of an optional texture, 1 when the texture is missing
+    fn sample_factor(
+        &self,
+        tex: &Option<Texture>,
+        uv: Vector2<f32>,
+        ddx: Vector2<f32>,
+        ddy: Vector2<f32>,
+    ) -> f32 {
+        tex.as_ref().map_or(1.0, |tex| {
+            self.inputs
+                .model
+                .sampler
+                .sample_texture(tex, uv, ddx, ddy)
+                .x
+        })
+    }
+}
+
+impl Shader for PbrShader<'_> {
+    type Varyings = SurfaceVaryings;
+
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
+        self.inputs.vertex(f_idx, v_idx)
+    }
+
+    fn fragment(
+        &self,
+        varyings: &Self::Varyings,
+        ddx: &Self::Varyings,
+        ddy: &Self::Varyings,
+        color: &mut ColorAlpha,
+    ) -> bool {
+        let (uv, p, p_view, _, _) = *varyings;
+        let (uv_dx, uv_dy) = (ddx.0, ddy.0);
+        let model = self.inputs.model;
+        let material = &model.material;
+        let factor = |tex: &Option<Texture>| self.sample_factor(tex, uv, uv_dx, uv_dy);
+
+        let diffuse = model.get_diffuse(uv, uv_dx, uv_dy);
+        let alpha = diffuse.w * material.opacity;
+        if cut_out(material, alpha) {
+            return true;
+        }
+        let base_color = material.diffuse.component_mul(&diffuse.xyz());
+        let metallic = (material.metallic * factor(&model.metallic_tex)).clamp(0.0, 1.0);
+        let roughness = (material.roughness * factor(&model.roughness_tex)).clamp(0.03, 1.0);
+        let ao = factor(&model.ao_tex);
+
+        let n = self.inputs.normal(varyings, ddx, ddy);
+        let v = (-p_view).normalize();
+        let mut rgb =
+            material.ambient.component_mul(&base_color) * (material.ambient_strength * ao);
+        for (i, light) in self.inputs.lights.iter().enumerate() {
+            let (l, radiance) = light.incident(&p_view);
+            let n_dot_l = n.dot(&l);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            // scaled by pi so that a white lambertian surface facing a light of intensity 1
+            // comes out white, like with the Blinn-Phong shader
+            let irradiance = radiance * (PI * n_dot_l) * self.inputs.shadow(i, p, n_dot_l);
+            let brdf = Self::brdf(&n, &v, &l, &base_color, metallic, roughness);
+            rgb += brdf.component_mul(&irradiance);
         }
+        *color = rgb.push(alpha);
         false
     }
 }
@@ -111,31 +442,36 @@ impl Shader for GouphShader<'_> {
 pub struct DepthShader<'a> {
     coord_conv: Matrix4<f32>,
     model: &'a WModel,
-    varying_tri: Matrix3<f32>,
 }
 
 impl<'a> DepthShader<'a> {
     pub fn new(coord_conv: Matrix4<f32>, model: &'a WModel) -> Self {
-        DepthShader {
-            coord_conv,
-            model,
-            varying_tri: Matrix3::identity(),
-        }
+        DepthShader { coord_conv, model }
     }
 }
 
 impl Shader for DepthShader<'_> {
-    fn vertex(&mut self, f_idx: usize, v_idx: usize) -> Vector3<f32> {
+    // texture coordinates, only read for alpha cutouts
+    type Varyings = Vector2<f32>;
+
+    fn vertex(&self, f_idx: usize, v_idx: usize) -> (Vector4<f32>, Self::Varyings) {
         let v = self.model.get_vertex(self.model.get_face(f_idx)[v_idx]);
-        let v = self.coord_conv * Vector4::new(v.x, v.y, v.z, 1.0);
-        let v = Vector3::new(v.x / v.w, v.y / v.w, v.z / v.w);
-        self.varying_tri[(0, v_idx)] = v.x;
-        self.varying_tri[(1, v_idx)] = v.y;
-        self.varying_tri[(2, v_idx)] = v.z;
-        v
+        let uv = self.model.get_uv(f_idx, v_idx);
+        (self.coord_conv * Vector4::new(v.x, v.y, v.z, 1.0), uv)
     }
 
-    fn fragment(&mut self, color: &mut Rgb<u8>, bar: Vector3<f32>) -> bool {
-        false
+    fn fragment(
+        &self,
+        uv: &Self::Varyings,
+        ddx: &Self::Varyings,
+        ddy: &Self::Varyings,
+        _color: &mut ColorAlpha,
+    ) -> bool {
+        let material = &self.model.material;
+        material.alpha_cutoff.is_some()
+            && cut_out(
+                material,
+                self.model.get_diffuse(*uv, *ddx, *ddy).w * material.opacity,
+            )
     }
 }
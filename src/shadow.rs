@@ -0,0 +1,192 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::{
+    geometry,
+    scene::{Light, LightKind},
+};
+
+// Filtering of shadow buffer lookups. Depths and biases are in shadow buffer depth units,
+// where the depth grows towards the light.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub resolution: u32,    // edge length of the square shadow map in texels
+    pub kernel_size: u32,   // PCF taps per side, 1 gives hard shadows
+    pub constant_bias: f32, // depth offset against shadow acne
+    pub slope_bias: f32,    // extra offset scaled by the slope of the surface seen from the light
+    pub strength: f32,      // 0 ignores shadows, 1 leaves fully shadowed areas black
+    pub pcss: bool,         // widen the kernel with the distance between blocker and receiver
+    pub light_size: f32,    // PCSS penumbra radius in texels per unit of blocker distance
+    pub max_radius: f32,    // PCSS blocker search and penumbra radius limit in texels
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            resolution: 2048,
+            kernel_size: 3,
+            constant_bias: 0.005,
+            slope_bias: 0.005,
+            strength: 0.7,
+            pcss: false,
+            light_size: 40.0,
+            max_radius: 8.0,
+        }
+    }
+}
+
+// Depth of the scene seen from a light, with a projection fitted to the scene bounds:
+// orthographic for directional lights, perspective from the light position otherwise.
+pub struct ShadowMap {
+    pub width: u32,
+    pub height: u32,
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub viewport: Matrix4<f32>,
+    pub depth: Vec<f32>,
+}
+
+impl ShadowMap {
+    // tangent of the angle between the normal and the light is capped to keep the bias finite
+    const MAX_SLOPE: f32 = 10.0;
+
+    // `bounds` is the world space (min, max) box that has to fit into the map
+    pub fn new(light: &Light, bounds: (Vector3<f32>, Vector3<f32>), resolution: u32) -> Self {
+        let center = (bounds.0 + bounds.1) / 2.0;
+        let radius = ((bounds.1 - bounds.0).norm() / 2.0).max(1e-3);
+
+        let (eye, forward) = match light.kind {
+            LightKind::Directional => (center + light.dir * 2.0 * radius, -light.dir),
+            LightKind::Point => (light.position, (center - light.position).normalize()),
+            LightKind::Spot { .. } => (light.position, -light.dir),
+        };
+        let forward = if forward.iter().all(|c| c.is_finite()) {
+            forward
+        } else {
+            Vector3::new(0.0, -1.0, 0.0)
+        };
+        let mut up = Vector3::new(0.0, 1.0, 0.0);
+        if forward.cross(&up).norm() < 1e-6 {
+            up = Vector3::new(1.0, 0.0, 0.0);
+        }
+        let view = geometry::get_lookat(eye, eye + forward, up);
+
+        let projection = match light.kind {
+            LightKind::Directional => {
+                // box around the corners of the bounds in light space, the camera looks along -z
+                let mut min = Vector3::repeat(f32::MAX);
+                let mut max = Vector3::repeat(f32::MIN);
+                for i in 0..8 {
+                    let corner = Vector3::new(
+                        if i & 1 == 0 { bounds.0.x } else { bounds.1.x },
+                        if i & 2 == 0 { bounds.0.y } else { bounds.1.y },
+                        if i & 4 == 0 { bounds.0.z } else { bounds.1.z },
+                    );
+                    let p = (view * corner.push(1.0)).xyz();
+                    min = min.inf(&p);
+                    max = max.sup(&p);
+                }
+                let pad = radius * 0.01;
+                geometry::get_orthographic(
+                    min.x - pad,
+                    max.x + pad,
+                    min.y - pad,
+                    max.y + pad,
+                    -max.z - pad,
+                    -min.z + pad,
+                )
+            }
+            _ => {
+                // frustum enclosing the bounding sphere, or the spot cone if that is narrower
+                let dist = (center - eye).norm();
+                let mut fovy = if dist > radius {
+                    2.0 * (radius / dist).asin() + 2.0 * (forward.angle(&(center - eye))).min(1.5)
+                } else {
+                    3.0
+                };
+                if let LightKind::Spot { outer, .. } = light.kind {
+                    fovy = fovy.min(2.0 * outer);
+                }
+                let near = (dist - radius).max(radius * 1e-2);
+                geometry::get_projection(fovy.clamp(1e-2, 3.0), 1.0, -near)
+            }
+        };
+
+        ShadowMap {
+            width: resolution,
+            height: resolution,
+            view,
+            projection,
+            viewport: geometry::get_viewport(0.0, 0.0, resolution as f32, resolution as f32),
+            depth: vec![f32::MIN; (resolution * resolution) as usize],
+        }
+    }
+
+    // world space to clip space of the light
+    pub fn light_conv(&self) -> Matrix4<f32> {
+        self.projection * self.view
+    }
+
+    // world space to shadow map texel coordinates, depth in z
+    pub fn transform(&self) -> Matrix4<f32> {
+        self.viewport * self.light_conv()
+    }
+
+    // Fraction of the light reaching the point `p` in shadow map coordinates (homogeneous,
+    // as returned by `transform`), scaled so that a fully shadowed point gets 1 - strength.
+    // `n_dot_l` is the cosine of the angle between the surface normal and the direction to
+    // the light. Points behind the light and texels outside the map count as lit.
+    pub fn intensity(&self, settings: &ShadowSettings, p: Vector4<f32>, n_dot_l: f32) -> f32 {
+        if p.w <= 1e-5 {
+            return 1.0;
+        }
+        let p = p.xyz() / p.w;
+        let n_dot_l = n_dot_l.clamp(1e-4, 1.0);
+        let slope = ((1.0 - n_dot_l * n_dot_l).sqrt() / n_dot_l).min(Self::MAX_SLOPE);
+        let z = p.z + settings.constant_bias + settings.slope_bias * slope;
+
+        let depth = |dx: f32, dy: f32| {
+            let (x, y) = ((p.x + dx).floor(), (p.y + dy).floor());
+            if !(x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32) {
+                return None;
+            }
+            Some(self.depth[x as usize + y as usize * self.width as usize])
+        };
+
+        let kernel = settings.kernel_size.max(1);
+        let half = (kernel - 1) as f32 / 2.0;
+        let mut spacing = 1.0;
+        if settings.pcss && kernel > 1 {
+            // average depth of the texels occluding the point inside the search region
+            let search = settings.max_radius / half;
+            let (mut sum, mut count) = (0.0, 0);
+            for i in 0..kernel {
+                for j in 0..kernel {
+                    let d = depth((i as f32 - half) * search, (j as f32 - half) * search);
+                    if let Some(d) = d.filter(|&d| d > z) {
+                        sum += d;
+                        count += 1;
+                    }
+                }
+            }
+            if count == 0 {
+                return 1.0;
+            }
+            let radius = (sum / count as f32 - z) * settings.light_size;
+            // the limit wins over the one texel spacing when the kernel is wider than it
+            let max_radius = settings.max_radius.max(0.0);
+            spacing = radius.clamp(half.min(max_radius), max_radius) / half;
+        }
+
+        let mut lit = 0;
+        for i in 0..kernel {
+            for j in 0..kernel {
+                let d = depth((i as f32 - half) * spacing, (j as f32 - half) * spacing);
+                if d.is_none_or(|d| d <= z) {
+                    lit += 1;
+                }
+            }
+        }
+        let visibility = lit as f32 / (kernel * kernel) as f32;
+        1.0 - settings.strength * (1.0 - visibility)
+    }
+}
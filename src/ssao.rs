@@ -0,0 +1,161 @@
+use nalgebra::{Matrix4, Vector3};
+use std::thread;
+
+use crate::framebuffer::FrameBuffer;
+
+// Screen space ambient occlusion computed from the z buffer of the main pass and the
+// normals reconstructed from it.
+#[derive(Clone, Copy, Debug)]
+pub struct Ssao {
+    pub radius: f32,        // view space radius of the sampled hemisphere
+    pub samples: usize,     // hemisphere samples per pixel
+    pub bias: f32,          // view space depth difference ignored against self occlusion
+    pub intensity: f32,     // exponent applied to the result, higher gives darker creases
+    pub blur_radius: usize, // half size of the box blur in pixels, 0 disables it
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Ssao {
+            radius: 0.1,
+            samples: 16,
+            bias: 0.005,
+            intensity: 1.5,
+            blur_radius: 2,
+        }
+    }
+}
+
+// deterministic pseudo-random number in [0, 1) for the sample kernel and the noise pattern
+fn hash(i: u32) -> f32 {
+    let mut x = i.wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+impl Ssao {
+    // edge length of the tiled rotation pattern, the blur averages it away
+    const NOISE_SIZE: usize = 4;
+
+    // points in the +z unit hemisphere, denser towards the center
+    fn kernel(&self) -> Vec<Vector3<f32>> {
+        (0..self.samples as u32)
+            .map(|i| {
+                let v = Vector3::new(
+                    hash(3 * i) * 2.0 - 1.0,
+                    hash(3 * i + 1) * 2.0 - 1.0,
+                    hash(3 * i + 2).max(0.05),
+                )
+                .normalize();
+                let t = (i + 1) as f32 / self.samples as f32;
+                v * (0.1 + 0.9 * t * t)
+            })
+            .collect()
+    }
+
+    // Darken every sample of `fb` by its ambient occlusion. `projection` and `viewport` are
+    // the matrices the frame buffer was rendered with.
+    pub fn apply(
+        &self,
+        fb: &mut FrameBuffer,
+        projection: &Matrix4<f32>,
+        viewport: &Matrix4<f32>,
+        threads: usize,
+    ) {
+        let (width, height) = (fb.width as usize, fb.height as usize);
+        let screen = viewport * projection;
+        let Some(inv_screen) = screen.try_inverse() else {
+            return;
+        };
+        let kernel = self.kernel();
+
+        let positions = fb.view_positions(&inv_screen);
+        let normals = fb.view_normals(&positions);
+
+        let occlusion = |x: usize, y: usize| {
+            let (Some(p), Some(n)) = (positions[x + y * width], normals[x + y * width]) else {
+                return 1.0;
+            };
+
+            let noise = (x % Self::NOISE_SIZE + (y % Self::NOISE_SIZE) * Self::NOISE_SIZE) as u32;
+            let angle = hash(noise + 1000) * std::f32::consts::TAU;
+            let r = Vector3::new(angle.cos(), angle.sin(), 0.0);
+            let mut t = r - n * n.dot(&r);
+            if t.norm_squared() < 1e-6 {
+                t = Vector3::x() - n * n.x;
+            }
+            let t = t.normalize();
+            let b = n.cross(&t);
+
+            let mut occluded = 0.0;
+            for k in kernel.iter() {
+                let s = p + (t * k.x + b * k.y + n * k.z) * self.radius;
+                let clip = projection * s.push(1.0);
+                if clip.w <= 1e-5 {
+                    continue;
+                }
+                let sp = viewport * (clip / clip.w);
+                if sp.x < 0.0 || sp.y < 0.0 || sp.x >= width as f32 || sp.y >= height as f32 {
+                    continue;
+                }
+                let Some(scene) = positions[sp.x as usize + sp.y as usize * width] else {
+                    continue;
+                };
+                // the camera looks along -z, so closer geometry has a larger z
+                if scene.z >= s.z + self.bias {
+                    let range = (self.radius / (p.z - scene.z).abs()).min(1.0);
+                    occluded += range * range * (3.0 - 2.0 * range);
+                }
+            }
+            (1.0 - occluded / kernel.len().max(1) as f32).powf(self.intensity)
+        };
+
+        let mut ao = vec![1.0f32; width * height];
+        let rows = height.div_ceil(threads.max(1)).max(1);
+        thread::scope(|s| {
+            for (i, chunk) in ao.chunks_mut(rows * width).enumerate() {
+                let occlusion = &occlusion;
+                s.spawn(move || {
+                    for (j, v) in chunk.iter_mut().enumerate() {
+                        let (x, y) = (j % width, i * rows + j / width);
+                        *v = occlusion(x, y);
+                    }
+                });
+            }
+        });
+
+        let ao = self.blur(&ao, width, height);
+        for (i, v) in ao.iter().enumerate() {
+            for color in fb.color[i * fb.samples..(i + 1) * fb.samples].iter_mut() {
+                *color = (color.xyz() * *v).push(color.w);
+            }
+        }
+    }
+
+    // separable box blur, the window is cut at the image borders
+    fn blur(&self, ao: &[f32], width: usize, height: usize) -> Vec<f32> {
+        let r = self.blur_radius;
+        if r == 0 {
+            return ao.to_vec();
+        }
+        let mut tmp = vec![0.0; ao.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, x1) = (x.saturating_sub(r), (x + r).min(width - 1));
+                let sum: f32 = ao[y * width + x0..=y * width + x1].iter().sum();
+                tmp[y * width + x] = sum / (x1 - x0 + 1) as f32;
+            }
+        }
+        let mut out = vec![0.0; ao.len()];
+        for y in 0..height {
+            let (y0, y1) = (y.saturating_sub(r), (y + r).min(height - 1));
+            for x in 0..width {
+                let sum: f32 = (y0..=y1).map(|yy| tmp[yy * width + x]).sum();
+                out[y * width + x] = sum / (y1 - y0 + 1) as f32;
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,159 @@
+use image::{DynamicImage, ImageBuffer, Rgba};
+use lazy_static::lazy_static;
+use nalgebra::Vector4;
+
+// how the texel values of a texture are interpreted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    Srgb,   // colors stored with the sRGB curve, decoded to linear values when sampled
+    Linear, // data such as normals or specular intensities, used as stored
+}
+
+lazy_static! {
+    static ref SRGB_TO_LINEAR: [f32; 256] =
+        std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0));
+}
+
+// sRGB transfer curve, both directions work on values in [0, 1]
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// An RGBA image with its chain of mip levels, each level half the size of the previous one
+// down to 1x1. Level 0 is the image itself. The alpha channel is always linear.
+pub struct Texture {
+    pub levels: Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    pub color_space: ColorSpace,
+}
+
+// Texels along one axis of a level `size` texels long feeding texel `i` of the next level,
+// with their weights. Even sizes average pairs, odd sizes weight three texels so that every
+// texel contributes equally and the last one isn't dropped.
+fn mip_taps(size: u32, i: u32) -> [(u32, f32); 3] {
+    if size == 1 {
+        [(0, 1.0), (0, 0.0), (0, 0.0)]
+    } else if size.is_multiple_of(2) {
+        [(2 * i, 0.5), (2 * i + 1, 0.5), (2 * i, 0.0)]
+    } else {
+        let n = size / 2;
+        let d = size as f32;
+        [
+            (2 * i, (n - i) as f32 / d),
+            (2 * i + 1, n as f32 / d),
+            (2 * i + 2, (i + 1) as f32 / d),
+        ]
+    }
+}
+
+impl Texture {
+    // Images without alpha become opaque. sRGB levels are averaged in linear space, so that
+    // they keep the brightness of the image.
+    pub fn new<I: Into<DynamicImage>>(image: I, color_space: ColorSpace) -> Self {
+        let mut levels = vec![image.into().into_rgba8()];
+        loop {
+            let prev = levels.last().unwrap();
+            let (w, h) = (prev.width(), prev.height());
+            if w == 1 && h == 1 {
+                break;
+            }
+            // sRGB channels are averaged in linear values, the rest in 8 bit values
+            let decode = |texel: Rgba<u8>, c: usize| match color_space {
+                ColorSpace::Srgb if c < 3 => SRGB_TO_LINEAR[texel[c] as usize],
+                _ => texel[c] as f32,
+            };
+            let next = ImageBuffer::from_fn((w / 2).max(1), (h / 2).max(1), |x, y| {
+                let mut sum = [0.0f32; 4];
+                for (sy, wy) in mip_taps(h, y) {
+                    for (sx, wx) in mip_taps(w, x) {
+                        let texel = prev[(sx, sy)];
+                        for (c, sum) in sum.iter_mut().enumerate() {
+                            *sum += wx * wy * decode(texel, c);
+                        }
+                    }
+                }
+                Rgba([0, 1, 2, 3].map(|c| match color_space {
+                    ColorSpace::Srgb if c < 3 => (linear_to_srgb(sum[c]) * 255.0).round() as u8,
+                    _ => sum[c].round() as u8,
+                }))
+            });
+            levels.push(next);
+        }
+        Texture {
+            levels,
+            color_space,
+        }
+    }
+
+    // texel of a level in [0, 1], the color is decoded to linear values for sRGB textures
+    pub fn texel(&self, level: usize, x: u32, y: u32) -> Vector4<f32> {
+        let c = self.levels[level].get_pixel(x, y);
+        let linear = Vector4::from(c.0.map(|v| v as f32 / 255.0));
+        match self.color_space {
+            ColorSpace::Srgb => Vector4::new(
+                SRGB_TO_LINEAR[c[0] as usize],
+                SRGB_TO_LINEAR[c[1] as usize],
+                SRGB_TO_LINEAR[c[2] as usize],
+                linear.w,
+            ),
+            ColorSpace::Linear => linear,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.levels[0].width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels[0].height()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn odd_mip_levels_keep_every_texel() {
+        let image = ImageBuffer::from_fn(3, 5, |x, y| Luma([(x * 40 + y * 25) as u8]));
+        let tex = Texture::new(image, ColorSpace::Linear);
+        let sizes: Vec<_> = tex.levels.iter().map(|l| l.dimensions()).collect();
+        assert_eq!(sizes, [(3, 5), (1, 2), (1, 1)]);
+        // every level has the mean of the image, up to rounding
+        let mean = |l: &ImageBuffer<Rgba<u8>, Vec<u8>>| {
+            l.pixels().map(|p| p[0] as f32).sum::<f32>() / (l.width() * l.height()) as f32
+        };
+        for level in &tex.levels {
+            assert!((mean(level) - mean(&tex.levels[0])).abs() <= 0.5);
+        }
+
+        // the far corner is the last texel of an odd row and column
+        let image = ImageBuffer::from_fn(3, 5, |x, y| {
+            Luma([if (x, y) == (2, 4) { 255u8 } else { 0 }])
+        });
+        let tex = Texture::new(image, ColorSpace::Linear);
+        assert_eq!(tex.levels[1][(0, 0)][0], 0);
+        assert_eq!(tex.levels[1][(0, 1)][0], 34); // 255 * 1/3 * 2/5
+        assert_eq!(tex.levels[2][(0, 0)][0], 17);
+    }
+
+    #[test]
+    fn even_mip_levels_average_pairs() {
+        let image =
+            ImageBuffer::from_fn(2, 2, |x, y| Luma([if (x, y) == (1, 1) { 2u8 } else { 0 }]));
+        let tex = Texture::new(image, ColorSpace::Linear);
+        assert_eq!(tex.levels[1][(0, 0)][0], 1);
+    }
+}